@@ -0,0 +1,12 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rust_benchmark::font_loader;
+
+fn bench_load(c: &mut Criterion) {
+	let data = include_bytes!("../assets/DroidSans-32.pf2");
+	c.bench_function("font_loader::PF2Loader::load", |b| {
+		b.iter(|| font_loader::PF2Loader::new(black_box(data)).load().unwrap())
+	});
+}
+
+criterion_group!(benches, bench_load);
+criterion_main!(benches);
@@ -0,0 +1,15 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use rust_benchmark::icosphere;
+
+fn bench_generate_mesh(c: &mut Criterion) {
+	let mut group = c.benchmark_group("icosphere::generate_mesh");
+	for subdivision_count in 0..=6 {
+		group.bench_with_input(BenchmarkId::from_parameter(subdivision_count), &subdivision_count, |b, &n| {
+			b.iter(|| icosphere::generate_mesh(black_box(n)))
+		});
+	}
+	group.finish();
+}
+
+criterion_group!(benches, bench_generate_mesh);
+criterion_main!(benches);
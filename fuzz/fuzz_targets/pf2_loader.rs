@@ -0,0 +1,17 @@
+// Run with `cargo +nightly fuzz run pf2_loader fuzz/corpus/pf2_loader assets` from the
+// repository root, the bundled fonts make a good seed corpus. Malformed fonts must be
+// rejected with an error, any panic is a bug.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_benchmark::font_loader::{AtlasPacking, PF2Loader, PF2LoaderBuilder};
+
+fuzz_target!(|data: &[u8]| {
+	let _ = PF2Loader::new(data).load();
+	let _ = PF2LoaderBuilder::new()
+		.packing(AtlasPacking::Shelf)
+		.supersample(2)
+		.padding(1)
+		.load(data);
+});
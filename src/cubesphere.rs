@@ -0,0 +1,57 @@
+use alloc::vec::Vec;
+use crate::HashMap;
+use glam::Vec3;
+use crate::icosphere::Triangle;
+
+fn lattice_vertex(cache: &mut HashMap<[u32; 3], u32>, vertexes: &mut Vec<Vec3>, segment_count: u32, key: [u32; 3]) -> u32 {
+	*cache.entry(key).or_insert_with(|| {
+		let position = Vec3::from_array(key.map(|i| i as f32 / segment_count as f32 * 2.0 - 1.0));
+		vertexes.push(position.normalize());
+		(vertexes.len() - 1) as u32
+	})
+}
+
+/// Generates a quad sphere: a cube whose faces are split into a `2^n`×`2^n` grid of quads
+/// (two triangles each), with every grid vertex projected onto the unit sphere. Vertices on
+/// cube edges are shared between faces and triangles use the same winding as `icosphere`,
+/// so each subdivision level quadruples the triangle count like it does there.
+pub fn generate_mesh(subdivision_count: u32) -> (Vec<Vec3>, Vec<Triangle>) {
+	let segment_count = 1u32 << subdivision_count;
+	let predicted_vertex_count = 6 * (segment_count * segment_count) as usize + 2;
+	let predicted_triangle_count = 12 * (segment_count * segment_count) as usize;
+	let mut vertexes = Vec::with_capacity(predicted_vertex_count);
+	let mut triangles = Vec::with_capacity(predicted_triangle_count);
+	let mut cache = HashMap::with_capacity(predicted_vertex_count);
+
+	for axis in 0..3 {
+		// Going along `u` then `v` turns counter-clockwise around the +axis face normal.
+		let u_axis = (axis + 1) % 3;
+		let v_axis = (axis + 2) % 3;
+		for side in [0, segment_count] {
+			let mut vertex = |u: u32, v: u32| {
+				let mut key = [0; 3];
+				key[axis] = side;
+				key[u_axis] = u;
+				key[v_axis] = v;
+				lattice_vertex(&mut cache, &mut vertexes, segment_count, key)
+			};
+			for v in 0..segment_count {
+				for u in 0..segment_count {
+					let quad = [vertex(u, v), vertex(u + 1, v), vertex(u + 1, v + 1), vertex(u, v + 1)];
+					if side == 0 {
+						triangles.push((quad[0], quad[1], quad[2]));
+						triangles.push((quad[0], quad[2], quad[3]));
+					} else {
+						triangles.push((quad[0], quad[2], quad[1]));
+						triangles.push((quad[0], quad[3], quad[2]));
+					}
+				}
+			}
+		}
+	}
+
+	debug_assert!(vertexes.len() == predicted_vertex_count);
+	debug_assert!(triangles.len() == predicted_triangle_count);
+
+	(vertexes, triangles)
+}
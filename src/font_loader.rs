@@ -1,231 +1,2543 @@
-use std::cell::RefCell;
-use std::io::{Cursor, Error, ErrorKind};
-use ahash::AHashMap as HashMap;
-use byteorder::{BigEndian, ReadBytesExt};
+use alloc::borrow::ToOwned;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt::{self, Display, Formatter};
+use crate::HashMap;
 use glam::Vec2;
 use rgb::RGBA8;
-use std::mem::size_of;
+use core::mem::size_of;
+use core::ops::Range;
+use core::time::Duration;
+#[cfg(not(any(feature = "std", test)))]
+#[allow(unused_imports)] // See `math`.
+use crate::math::FloatExt;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+#[cfg(feature = "log")]
+use log::{trace, warn};
+
+// Without the `log` feature parse diagnostics compile to nothing.
+#[cfg(not(feature = "log"))]
+macro_rules! trace {
+	($($arg:tt)*) => {};
+}
+#[cfg(not(feature = "log"))]
+macro_rules! warn {
+	($($arg:tt)*) => {};
+}
 
 #[derive(Debug)]
+pub enum PF2Error {
+	#[cfg(feature = "std")]
+	Io(std::io::Error),
+	UnexpectedEof,
+	BadMagic(Vec<u8>),
+	UnexpectedSection { expected: [u8; 4], found: [u8; 4] },
+	DuplicateCharacterIndex,
+	InvalidCharacterIndex,
+	UnsupportedStorageFlags { code_point: u32, flags: u8 },
+	MissingCharacterIndex,
+	InvalidDimensions,
+	GlyphTooLarge(u32),
+	BitmapTooShort(u32),
+	MultiplePointSizes { first: u16, second: u16 },
+	DuplicateCodePoint(u32),
+	NoFonts,
+	AtlasTooLarge(usize),
+	TruncatedSection([u8; 4]),
+	UnknownSection([u8; 4]),
+	OverlappingGlyphData(u32),
+	HeadersNotParsed,
+	FontMismatch,
+	NoCoverageAlpha,
+	WrongByteOrder { expected: ByteOrder, found: ByteOrder }
+}
+
+impl Display for PF2Error {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			#[cfg(feature = "std")]
+			PF2Error::Io(e) => write!(f, "{}", e),
+			PF2Error::UnexpectedEof => write!(f, "Font data ends in the middle of a section"),
+			PF2Error::BadMagic(magic) => write!(
+				f, "FILE section contents must be equal to \"PFF2\", but \"{}\" ({:02X?}) found",
+				String::from_utf8_lossy(magic), magic
+			),
+			PF2Error::UnexpectedSection { expected, found } => {
+				write!(
+					f, "Expected \"{}\" section, but \"{}\" ({:02X?}) found",
+					String::from_utf8_lossy(expected), String::from_utf8_lossy(found), found
+				)?;
+				if found.starts_with(&[0x1F, 0x8B]) {
+					write!(f, ", the file looks gzip-compressed")?;
+				}
+				Ok(())
+			}
+			PF2Error::DuplicateCharacterIndex => write!(f, "Character index occured more than once"),
+			PF2Error::InvalidCharacterIndex => write!(f, "Character index length is not divisible by a record size"),
+			PF2Error::UnsupportedStorageFlags { code_point, flags } => write!(
+				f, "Glyph {} uses unsupported storage flags {:#05b}", code_point, flags
+			),
+			PF2Error::MissingCharacterIndex => write!(f, "Character index is empty"),
+			PF2Error::InvalidDimensions => write!(f, "Max width or max height is unspecified or zero"),
+			PF2Error::GlyphTooLarge(code_point) => write!(f, "Glyph {} does not fit into the maximum glyph size", code_point),
+			PF2Error::BitmapTooShort(code_point) => write!(f, "Bitmap of glyph {} is too short for its size", code_point),
+			PF2Error::MultiplePointSizes { first, second } => write!(
+				f, "Font declares point sizes {} and {}, but a PFF2 file can only hold one", first, second
+			),
+			PF2Error::DuplicateCodePoint(code_point) => write!(f, "Glyph {} is defined more than once", code_point),
+			PF2Error::NoFonts => write!(f, "No fonts to load"),
+			PF2Error::AtlasTooLarge(max_dimension) => write!(
+				f, "Glyphs do not fit into an atlas of at most {}x{} texels", max_dimension, max_dimension
+			),
+			PF2Error::TruncatedSection(section) => write!(
+				f, "\"{}\" section extends past the end of the file", String::from_utf8_lossy(section)
+			),
+			PF2Error::UnknownSection(section) => write!(f, "Unknown section \"{}\"", String::from_utf8_lossy(section)),
+			PF2Error::OverlappingGlyphData(code_point) => write!(
+				f, "Data of glyph {} overlaps another glyph or lies outside the DATA section", code_point
+			),
+			PF2Error::HeadersNotParsed => write!(f, "Headers must be parsed with parse_headers() before rasterizing"),
+			PF2Error::FontMismatch => write!(f, "Font does not match the last atlas built by this loader"),
+			PF2Error::NoCoverageAlpha => write!(
+				f, "Text can only be drawn from an atlas with coverage in alpha, without outline or SDF"
+			),
+			PF2Error::WrongByteOrder { expected, found } => write!(f, "Expected {:?} font data, but it is {:?}", expected, found)
+		}
+	}
+}
+
+impl core::error::Error for PF2Error {
+	fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+		match self {
+			#[cfg(feature = "std")]
+			PF2Error::Io(e) => Some(e),
+			_ => None
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for PF2Error {
+	fn from(e: std::io::Error) -> Self {
+		PF2Error::Io(e)
+	}
+}
+
+/// Placement of a glyph in the atlas and relative to the pen. `tex_coord` and `tex_size` are
+/// in normalized texture coordinates; `offset`, `size` and `width` are in em units, i.e. pixels
+/// divided by the font's point size, so multiply them by the target pixel size (or use
+/// `scaled`) when laying out text. The fields are floats, so glyphs are `PartialEq` but
+/// neither `Eq` nor `Hash`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FontGlyph {
 	tex_coord: Vec2,
 	tex_size: Vec2,
+	tex_rect: (u32, u32, u32, u32),
 	offset: Vec2,
 	size: Vec2,
-	width: f32
+	width: f32,
+	advance_px: f32
+}
+
+impl FontGlyph {
+	pub fn tex_coord(&self) -> Vec2 {
+		self.tex_coord
+	}
+
+	pub fn tex_size(&self) -> Vec2 {
+		self.tex_size
+	}
+
+	/// Bottom-left corner of the glyph quad relative to the pen on the baseline, y pointing
+	/// up as in PFF2: a negative y means the glyph descends below the baseline.
+	pub fn offset(&self) -> Vec2 {
+		self.offset
+	}
+
+	/// Top-left corner of the glyph quad relative to the pen on the baseline with y pointing
+	/// down, as in screen space and in the atlas: add it to the pen, scaled by the pixel
+	/// size, to get where the top-left texel at `tex_coord` goes. A negative y is above
+	/// the baseline.
+	pub fn baseline_offset(&self) -> Vec2 {
+		Vec2::new(self.offset.x, -(self.offset.y + self.size.y))
+	}
+
+	pub fn size(&self) -> Vec2 {
+		self.size
+	}
+
+	/// The glyph's rectangle in the atlas as `(x, y, width, height)` in texels, exactly as it
+	/// was packed. `tex_coord` and `tex_size` are computed from it.
+	pub fn tex_rect(&self) -> (u32, u32, u32, u32) {
+		self.tex_rect
+	}
+
+	/// `tex_coord` and `tex_size` as `(x, y, width, height)` in texels of an atlas of
+	/// `texture_width`×`texture_height`, e.g. a rescaled copy of the atlas. For the atlas
+	/// the glyph was loaded into, `tex_rect` gives the same rectangle without rounding.
+	pub fn tex_rect_px(&self, texture_width: usize, texture_height: usize) -> (u32, u32, u32, u32) {
+		let (width, height) = (texture_width as f32, texture_height as f32);
+		(
+			(self.tex_coord.x * width).round() as u32,
+			(self.tex_coord.y * height).round() as u32,
+			(self.tex_size.x * width).round() as u32,
+			(self.tex_size.y * height).round() as u32
+		)
+	}
+
+	pub fn width(&self) -> f32 {
+		self.width
+	}
+
+	/// Horizontal pen advance in pixels of the font's own point size, as stored in the file.
+	pub fn advance_px(&self) -> f32 {
+		self.advance_px
+	}
+
+	/// `tex_coord` as a plain array, for renderers that don't use glam.
+	pub fn tex_coord_array(&self) -> [f32; 2] {
+		self.tex_coord.to_array()
+	}
+
+	/// `tex_size` as a plain array.
+	pub fn tex_size_array(&self) -> [f32; 2] {
+		self.tex_size.to_array()
+	}
+
+	/// `offset` as a plain array.
+	pub fn offset_array(&self) -> [f32; 2] {
+		self.offset.to_array()
+	}
+
+	/// `size` as a plain array.
+	pub fn size_array(&self) -> [f32; 2] {
+		self.size.to_array()
+	}
+
+	/// Tight box around the glyph's ink (and outline, if any) as `(min_x, min_y, max_x, max_y)`
+	/// in pixels relative to the pen position on the baseline, with y pointing up.
+	pub fn ink_bounds(&self, metrics: &FontMetrics) -> (f32, f32, f32, f32) {
+		let min = self.offset * metrics.point_size;
+		let max = (self.offset + self.size) * metrics.point_size;
+		(min.x, min.y, max.x, max.y)
+	}
+
+	/// The same glyph with `offset`, `size`, `width` and `advance_px` in pixels of a font
+	/// rendered at `target_px`. Texture coordinates are unchanged.
+	pub fn scaled(&self, target_px: f32) -> FontGlyph {
+		FontGlyph {
+			tex_coord: self.tex_coord,
+			tex_size: self.tex_size,
+			tex_rect: self.tex_rect,
+			offset: self.offset * target_px,
+			size: self.size * target_px,
+			width: self.width * target_px,
+			advance_px: self.width * target_px
+		}
+	}
+
+	fn empty_box(metrics: &FontMetrics) -> Self {
+		FontGlyph {
+			tex_coord: Vec2::ZERO,
+			tex_size: Vec2::ZERO,
+			tex_rect: (0, 0, 0, 0),
+			offset: Vec2::ZERO,
+			size: Vec2::new(0.5, metrics.ascent),
+			width: 0.5,
+			advance_px: 0.5 * metrics.point_size
+		}
+	}
+}
+
+/// Vertical and horizontal line metrics of a font. `point_size` is in pixels,
+/// every other value is divided by the point size like the `FontGlyph` fields.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FontMetrics {
+	pub ascent: f32,
+	pub descent: f32,
+	pub point_size: f32,
+	pub max_width: f32,
+	pub max_height: f32
+}
+
+/// Measures a single line of text in the same point-size-normalized units as the glyphs:
+/// the width is the sum of glyph advances and the height is `ascent + descent`.
+/// Characters missing from `glyphs` advance by the `notdef` glyph if it is given and
+/// present, and are skipped otherwise.
+pub fn measure(glyphs: &HashMap<u32, FontGlyph>, metrics: &FontMetrics, text: &str, notdef: Option<u32>) -> Vec2 {
+	let notdef = notdef.and_then(|code_point| glyphs.get(&code_point));
+	let width = text.chars()
+		.filter_map(|c| glyphs.get(&(c as u32)).or(notdef))
+		.map(|glyph| glyph.width)
+		.sum();
+	Vec2::new(width, metrics.ascent + metrics.descent)
+}
+
+/// A seekable byte stream fonts are read from. `SliceReader` reads from memory and,
+/// with the `std` feature, every `std::io::Read + Seek` stream implements it as well.
+pub trait PF2Source {
+	fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), PF2Error>;
+	fn seek(&mut self, position: u64) -> Result<(), PF2Error>;
+	fn position(&mut self) -> Result<u64, PF2Error>;
+	/// Total length of the stream in bytes.
+	fn stream_len(&mut self) -> Result<u64, PF2Error>;
 }
 
-pub struct PF2Loader<'a> {
+#[derive(Debug, Clone)]
+pub struct SliceReader<'a> {
 	data: &'a [u8],
-	cursor: RefCell<Cursor<&'a [u8]>>,
+	position: usize
+}
+
+impl<'a> SliceReader<'a> {
+	pub fn new(data: &'a [u8]) -> Self {
+		SliceReader { data, position: 0 }
+	}
+}
+
+impl PF2Source for SliceReader<'_> {
+	fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), PF2Error> {
+		let bytes = self.data.get(self.position..)
+			.and_then(|rest| rest.get(..buf.len()))
+			.ok_or(PF2Error::UnexpectedEof)?;
+		buf.copy_from_slice(bytes);
+		self.position += buf.len();
+		Ok(())
+	}
+
+	fn seek(&mut self, position: u64) -> Result<(), PF2Error> {
+		// Seeking past the end is allowed like for files, only reading there fails.
+		self.position = usize::try_from(position).unwrap_or(usize::MAX);
+		Ok(())
+	}
+
+	fn position(&mut self) -> Result<u64, PF2Error> {
+		Ok(self.position as u64)
+	}
+
+	fn stream_len(&mut self) -> Result<u64, PF2Error> {
+		Ok(self.data.len() as u64)
+	}
+}
+
+#[cfg(feature = "std")]
+impl<R: std::io::Read + std::io::Seek> PF2Source for R {
+	fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), PF2Error> {
+		// Truncated fonts fail the same way as when read from a slice.
+		std::io::Read::read_exact(self, buf).map_err(|e| match e.kind() {
+			std::io::ErrorKind::UnexpectedEof => PF2Error::UnexpectedEof,
+			_ => PF2Error::Io(e)
+		})
+	}
+
+	fn seek(&mut self, position: u64) -> Result<(), PF2Error> {
+		std::io::Seek::seek(self, std::io::SeekFrom::Start(position))?;
+		Ok(())
+	}
+
+	fn position(&mut self) -> Result<u64, PF2Error> {
+		Ok(std::io::Seek::stream_position(self)?)
+	}
+
+	fn stream_len(&mut self) -> Result<u64, PF2Error> {
+		let position = std::io::Seek::stream_position(self)?;
+		let len = std::io::Seek::seek(self, std::io::SeekFrom::End(0))?;
+		std::io::Seek::seek(self, std::io::SeekFrom::Start(position))?;
+		Ok(len)
+	}
+}
+
+fn read_bytes<const N: usize>(reader: &mut impl PF2Source) -> Result<[u8; N], PF2Error> {
+	let mut bytes = [0; N];
+	reader.read_exact(&mut bytes)?;
+	Ok(bytes)
+}
+
+/// Byte order of the numbers in a font file. PFF2 is defined as big-endian, but some
+/// tools write little-endian files. Section tags are byte strings either way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteOrder {
+	BigEndian,
+	LittleEndian
+}
+
+fn read_u8(reader: &mut impl PF2Source) -> Result<u8, PF2Error> {
+	Ok(read_bytes::<1>(reader)?[0])
+}
+
+fn read_u16(reader: &mut impl PF2Source, byte_order: ByteOrder) -> Result<u16, PF2Error> {
+	let bytes = read_bytes(reader)?;
+	Ok(match byte_order {
+		ByteOrder::BigEndian => u16::from_be_bytes(bytes),
+		ByteOrder::LittleEndian => u16::from_le_bytes(bytes)
+	})
+}
+
+fn read_i16(reader: &mut impl PF2Source, byte_order: ByteOrder) -> Result<i16, PF2Error> {
+	Ok(read_u16(reader, byte_order)? as i16)
+}
+
+fn read_u32(reader: &mut impl PF2Source, byte_order: ByteOrder) -> Result<u32, PF2Error> {
+	let bytes = read_bytes(reader)?;
+	Ok(match byte_order {
+		ByteOrder::BigEndian => u32::from_be_bytes(bytes),
+		ByteOrder::LittleEndian => u32::from_le_bytes(bytes)
+	})
+}
+
+/// Walks the sections of a PFF2 file without interpreting them, yielding every tag with the
+/// section contents, e.g. to dump a file's structure. The byte order is detected from the
+/// FILE section like `PF2Loader` does. DATA, whose length is all ones, runs to the end of
+/// the file and ends the walk, and so does the first error.
+pub fn sections(data: &[u8]) -> impl Iterator<Item = Result<([u8; 4], &[u8]), PF2Error>> {
+	let byte_order = detect_byte_order(data).unwrap_or(ByteOrder::BigEndian);
+	let mut reader = SliceReader::new(data);
+	let mut done = false;
+	core::iter::from_fn(move || {
+		if done || reader.position >= data.len() {
+			return None;
+		}
+		let section = read_section_header(&mut reader, byte_order, data.len() as u64).map(|(tag, start, len)| {
+			let start = start as usize;
+			let end = len.map_or(data.len(), |len| start + len);
+			reader.position = end;
+			(tag, &data[start..end], len.is_none())
+		});
+		done = !matches!(section, Ok((_, _, false)));
+		Some(section.map(|(tag, contents, _)| (tag, contents)))
+	})
+}
+
+/// Tells the byte order from the length of the FILE section at the start of `header`,
+/// which is always 4. `None` if the header is too short or the length is something else.
+fn detect_byte_order(header: &[u8]) -> Option<ByteOrder> {
+	match header.get(4..8)? {
+		[0, 0, 0, 4] => Some(ByteOrder::BigEndian),
+		[4, 0, 0, 0] => Some(ByteOrder::LittleEndian),
+		_ => None
+	}
+}
+
+/// Reads the section header at the reader's position, returning the tag, where the
+/// contents start and their length. The length is `None` for the all-ones length of DATA,
+/// which runs to the end of the stream; any other section must end within `stream_len`.
+fn read_section_header(reader: &mut impl PF2Source, byte_order: ByteOrder, stream_len: u64) -> Result<([u8; 4], u64, Option<usize>), PF2Error> {
+	let tag = read_bytes(reader)?;
+	let len = read_u32(reader, byte_order)?;
+	let start = reader.position()?;
+	if len == 0xFFFFFFFF {
+		return Ok((tag, start, None));
+	}
+	if start + len as u64 > stream_len {
+		return Err(PF2Error::TruncatedSection(tag));
+	}
+	Ok((tag, start, Some(len as usize)))
+}
+
+const fn make_section_type(t: &[u8; 4]) -> u32 {
+	(t[0] as u32) << 24 | (t[1] as u32) << 16 | (t[2] as u32) << 8 | (t[3] as u32)
+}
+
+mod section {
+	use super::make_section_type;
+
+	pub const FILE: u32 = make_section_type(b"FILE");
+	pub const NAME: u32 = make_section_type(b"NAME");
+	pub const FAMI: u32 = make_section_type(b"FAMI");
+	pub const WEIG: u32 = make_section_type(b"WEIG");
+	pub const SLAN: u32 = make_section_type(b"SLAN");
+	pub const PTSZ: u32 = make_section_type(b"PTSZ");
+	pub const MAXW: u32 = make_section_type(b"MAXW");
+	pub const MAXH: u32 = make_section_type(b"MAXH");
+	pub const ASCE: u32 = make_section_type(b"ASCE");
+	pub const DESC: u32 = make_section_type(b"DESC");
+	pub const CHIX: u32 = make_section_type(b"CHIX");
+	pub const DATA: u32 = make_section_type(b"DATA");
+}
+
+pub struct PF2Loader<R> {
+	reader: RefCell<R>,
 	section_type: u32,
 	section_len: usize,
 	section_start: usize,
+	stream_len: u64,
+	sections_seen: Vec<[u8; 4]>,
+	name: Option<String>,
+	family: Option<String>,
+	weight: Option<String>,
+	slant: Option<String>,
 	point_size: u16,
 	max_width: u16,
 	max_height: u16,
 	ascent: u16,
 	descent: u16,
+	headers_parsed: bool,
+	strict: bool,
+	byte_order: Option<ByteOrder>,
+	file_byte_order: ByteOrder,
 	character_index: HashMap<u32, (u32, usize)>,
+	supersampling: usize,
+	padding: usize,
+	packing: AtlasPacking,
+	max_texture_dimension: Option<usize>,
+	pow2_dimensions: bool,
+	trim_rows: bool,
+	codepoint_filter: Option<fn(u32) -> bool>,
+	outline: usize,
+	sdf_spread: usize,
+	foreground: RGBA8,
+	background: RGBA8,
+	cell_width: usize,
+	cell_height: usize,
 	col_count: usize,
 	texture_width: usize,
 	texture_height: usize,
-	texture_data: RefCell<Vec<RGBA8>>,
-	glyphs: HashMap<u32, FontGlyph>
+	glyphs: HashMap<u32, FontGlyph>,
+	timings: LoadTimings
 }
 
-struct PF2CharDef {
-	width: u16,
-	height: u16,
-	x_offset: i16,
-	y_offset: i16,
-	device_width: i16
+/// Where the time of the last `load()` went, for profiling. Without the `std` feature there
+/// is no clock and every duration stays zero.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoadTimings {
+	/// Parsing every section before DATA, zero if `parse_headers()` was called beforehand.
+	pub parse: Duration,
+	/// Reading the glyph definitions and bitmaps from DATA.
+	pub read: Duration,
+	/// Packing the glyphs into the atlas.
+	pub layout: Duration,
+	/// Allocating and clearing the atlas texels.
+	pub allocation: Duration,
+	/// Rasterizing every glyph into the atlas.
+	pub rasterization: Duration
 }
 
-impl PF2Loader<'_> {
-	pub fn new(data: &[u8]) -> PF2Loader {
-		PF2Loader { 
-			data,
-			cursor: RefCell::new(Cursor::new(data)),
-			section_type: 0,
-			section_len: 0,
-			section_start: 0,
-			point_size: 0,
-			max_width: 0,
-			max_height: 0,
-			ascent: 0,
-			descent: 0,
-			character_index: HashMap::new(),
-			col_count: 0,
-			texture_width: 0,
-			texture_height: 0,
-			texture_data: RefCell::new(Vec::new()),
-			glyphs: HashMap::new()
+#[cfg(feature = "std")]
+struct Stopwatch(std::time::Instant);
+
+#[cfg(feature = "std")]
+impl Stopwatch {
+	fn start() -> Self {
+		Stopwatch(std::time::Instant::now())
+	}
+
+	/// Time since the start or the previous lap.
+	fn lap(&mut self) -> Duration {
+		let now = std::time::Instant::now();
+		let elapsed = now - self.0;
+		self.0 = now;
+		elapsed
+	}
+}
+
+#[cfg(not(feature = "std"))]
+struct Stopwatch;
+
+#[cfg(not(feature = "std"))]
+impl Stopwatch {
+	fn start() -> Self {
+		Stopwatch
+	}
+
+	fn lap(&mut self) -> Duration {
+		Duration::ZERO
+	}
+}
+
+/// How glyphs are laid out in the atlas. `Grid` gives every glyph a cell as large as the
+/// largest glyph in character index order, `Shelf` packs glyph rectangles by their actual size
+/// into rows sorted by height, which is much smaller for fonts with uneven glyph sizes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AtlasPacking {
+	Grid,
+	Shelf
+}
+
+/// Channel layout of RGBA atlases, as a shorthand for `with_colors`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AtlasLayout {
+	/// Coverage in every channel over transparent black: white glyphs, like the
+	/// grayscale atlas expanded to RGBA.
+	#[default]
+	WhiteOnTransparent,
+	/// Coverage in alpha with RGB left at 0, for tinting in the shader and premultiplied
+	/// alpha pipelines. SDF atlases keep the distance in alpha either way.
+	AlphaOnly
+}
+
+impl AtlasLayout {
+	fn colors(self) -> (RGBA8, RGBA8) {
+		match self {
+			AtlasLayout::WhiteOnTransparent => (RGBA8::new(255, 255, 255, 255), RGBA8::new(0, 0, 0, 0)),
+			AtlasLayout::AlphaOnly => (RGBA8::new(0, 0, 0, 255), RGBA8::new(0, 0, 0, 0))
 		}
 	}
+}
+
+/// What `load_many` does when several fonts have a glyph for the same code point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodePointCollision {
+	/// The glyph from the font that comes later in the list is used.
+	LaterWins,
+	/// Loading fails with `PF2Error::DuplicateCodePoint`.
+	Error
+}
+
+/// Texel formats the rasterizer writes directly. Glyphs go straight into the final format
+/// with a precomputed solid texel rather than through a coverage buffer: for DroidSans-32,
+/// allocating and rasterizing the RGBA atlas takes about 1.35 ms, the coverage atlas about
+/// 1.05 ms, but expanding the latter to RGBA adds another 0.45 ms. `load_grayscale()` is
+/// the coverage-only path.
+trait AtlasPixel: Copy + Default + Send + Sync {
+	fn from_coverage(coverage: u8, foreground: RGBA8, background: RGBA8) -> Self;
+	fn from_outlined(fill: u8, outline: u8) -> Self;
+	fn from_distance(distance: u8, foreground: RGBA8) -> Self;
+	fn background(background: RGBA8) -> Self;
+}
+
+impl AtlasPixel for RGBA8 {
+	fn from_coverage(coverage: u8, foreground: RGBA8, background: RGBA8) -> Self {
+		blend(background, foreground, coverage)
+	}
 
-	fn make_section_type(t: &[u8; 4]) -> u32 {
-		return (t[0] as u32) << 24 | (t[1] as u32) << 16 | (t[2] as u32) << 8 | (t[3] as u32)
+	fn from_outlined(fill: u8, outline: u8) -> Self {
+		RGBA8::new(fill, fill, fill, fill.max(outline))
 	}
 
-	fn section_type_as_str(&self) -> Result<&str, Error> {
-		std::str::from_utf8(
-			&self.data[self.section_start - 8..self.section_start - 4]
-		).map_err(|e| Error::new(ErrorKind::Other, e))
+	fn from_distance(distance: u8, foreground: RGBA8) -> Self {
+		RGBA8::new(foreground.r, foreground.g, foreground.b, distance)
 	}
 
-	fn read_section(&mut self) -> Result<bool, Error> {
-		let cursor = self.cursor.get_mut();
-		cursor.set_position((self.section_start + self.section_len) as u64);
-		self.section_type = cursor.read_u32::<BigEndian>()?;
-		self.section_len = cursor.read_u32::<BigEndian>()? as usize;
-		self.section_start = cursor.position() as usize;
-		Ok(self.section_len < 0xFFFFFFFF)
+	fn background(background: RGBA8) -> Self {
+		background
 	}
+}
 
-	fn section_as_str(&self) -> Result<&str, Error> {
-		std::str::from_utf8(&self.data[self.section_start..self.section_start + self.section_len])
-			.map_err(|e| Error::new(ErrorKind::Other, e))
+impl AtlasPixel for u8 {
+	fn from_coverage(coverage: u8, _foreground: RGBA8, _background: RGBA8) -> Self {
+		coverage
 	}
 
-	fn parse_character_index(&mut self) -> Result<(), Error> {
-		if !self.character_index.is_empty() {
-			return Err(Error::new(ErrorKind::Other, "Character index occured more than once"));
+	fn from_outlined(fill: u8, _outline: u8) -> Self {
+		fill
+	}
+
+	fn from_distance(distance: u8, _foreground: RGBA8) -> Self {
+		distance
+	}
+
+	fn background(_background: RGBA8) -> Self {
+		0
+	}
+}
+
+/// Offset of a distance transform cell that hasn't reached any seed yet.
+const FAR_POINT: (i32, i32) = (i32::MAX / 4, i32::MAX / 4);
+
+fn offset_length((dx, dy): (i32, i32)) -> f32 {
+	((dx as f32) * (dx as f32) + (dy as f32) * (dy as f32)).sqrt()
+}
+
+/// 8SSEDT: turns a grid of `(0, 0)` seeds and `FAR_POINT` cells into the offset from every
+/// cell to its nearest seed, in two raster passes.
+fn distance_transform(grid: &mut [(i32, i32)], width: usize, height: usize) {
+	let length_squared = |(dx, dy): (i32, i32)| dx as i64 * dx as i64 + dy as i64 * dy as i64;
+	let compare = |grid: &mut [(i32, i32)], x: usize, y: usize, ox: isize, oy: isize| {
+		let (nx, ny) = (x as isize + ox, y as isize + oy);
+		if nx < 0 || ny < 0 || nx as usize >= width || ny as usize >= height {
+			return;
 		}
-		const RECORD_LEN: usize = size_of::<u32>() + size_of::<u8>() + size_of::<u32>();
-		if self.section_len % RECORD_LEN != 0 {
-			return Err(Error::new(ErrorKind::Other, "Character index length is not divisible by a record size"));
+		let (dx, dy) = grid[ny as usize * width + nx as usize];
+		let candidate = (dx + ox as i32, dy + oy as i32);
+		let cell = &mut grid[y * width + x];
+		if length_squared(candidate) < length_squared(*cell) {
+			*cell = candidate;
 		}
-		let count = self.section_len / RECORD_LEN;
-		self.character_index.reserve(count);
-		let cursor = self.cursor.get_mut();
-		for _ in 0..count {
-			let unicode_code_point = cursor.read_u32::<BigEndian>()?;
-			let flags = cursor.read_u8()?;
-			let offset = cursor.read_u32::<BigEndian>()?;
-			assert!(flags & 0b111 == 0);
-			self.character_index.insert(unicode_code_point, (offset, self.character_index.len()));
+	};
+	for y in 0..height {
+		for x in 0..width {
+			compare(grid, x, y, -1, 0);
+			compare(grid, x, y, 0, -1);
+			compare(grid, x, y, -1, -1);
+			compare(grid, x, y, 1, -1);
+		}
+		for x in (0..width).rev() {
+			compare(grid, x, y, 1, 0);
+		}
+	}
+	for y in (0..height).rev() {
+		for x in (0..width).rev() {
+			compare(grid, x, y, 1, 0);
+			compare(grid, x, y, 0, 1);
+			compare(grid, x, y, -1, 1);
+			compare(grid, x, y, 1, 1);
+		}
+		for x in 0..width {
+			compare(grid, x, y, -1, 0);
 		}
-		//trace!("Character index contains {} items", self.character_index.len());
-		Ok(())
 	}
+}
+
+/// A character definition header: bitmap size in pixels, offset of its bottom-left corner
+/// from the pen on the baseline (y up) and the horizontal advance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PF2CharDef {
+	pub width: u16,
+	pub height: u16,
+	pub x_offset: i16,
+	pub y_offset: i16,
+	pub device_width: i16
+}
+
+/// Length of a character definition header in the file, the bitmap follows right after it.
+const CHAR_DEF_LEN: u64 = 2 * size_of::<u16>() as u64 + 3 * size_of::<i16>() as u64;
+
+/// Code point the tofu box for fonts without a notdef glyph is rasterized under. It is
+/// taken out of the glyphs again, so only this entry may use it.
+const NOTDEF_BOX: u32 = u32::MAX;
+
+struct PF2CharEntry {
+	code_point: u32,
+	index: usize,
+	point_size: u16,
+	bitmap_offset: u64,
+	bitmap: Range<usize>,
+	def: PF2CharDef
+}
+
+/// The part of the loader state needed to rasterize glyphs, split out so it can be
+/// shared between threads while the loader itself keeps the reader.
+#[derive(Clone, Copy)]
+struct GlyphRasterizer {
+	supersampling: usize,
+	outline: usize,
+	sdf_spread: usize,
+	foreground: RGBA8,
+	background: RGBA8,
+	point_size: u16,
+	texture_width: usize,
+	texture_height: usize
+}
 
-	fn parse_section(&mut self) -> Result<(), Error> {
-		let cursor = self.cursor.get_mut();
-		if self.section_type == Self::make_section_type(b"NAME") {
-			//trace!("Font name: {}", self.section_as_str()?);
-		} else if self.section_type == Self::make_section_type(b"FAMI") {
-			//trace!("Font family: {}", self.section_as_str()?);
-		} else if self.section_type == Self::make_section_type(b"WEIG") {
-			//trace!("Font weight: {}", self.section_as_str()?);
-		} else if self.section_type == Self::make_section_type(b"SLAN") {
-			//trace!("Font slant: {}", self.section_as_str()?);
-		} else if self.section_type == Self::make_section_type(b"PTSZ") {
-			self.point_size = cursor.read_u16::<BigEndian>()?;
-			//trace!("Point size: {}", self.point_size);
-		} else if self.section_type == Self::make_section_type(b"MAXW") {
-			self.max_width = cursor.read_u16::<BigEndian>()?;
-			//trace!("Max width: {}", self.max_width);
-		} else if self.section_type == Self::make_section_type(b"MAXH") {
-			self.max_height = cursor.read_u16::<BigEndian>()?;
-			//trace!("Max height: {}", self.max_height);
-		} else if self.section_type == Self::make_section_type(b"ASCE") {
-			self.ascent = cursor.read_u16::<BigEndian>()?;
-			//trace!("Ascent: {}", self.ascent);
-		} else if self.section_type == Self::make_section_type(b"DESC") {
-			self.descent = cursor.read_u16::<BigEndian>()?;
-			//trace!("Descent: {}", self.descent);
-		} else if self.section_type == Self::make_section_type(b"CHIX") {
-			self.parse_character_index()?;
+impl GlyphRasterizer {
+	/// Texels added on every side of a glyph for its outline or distance field.
+	fn margin(&self) -> usize {
+		if self.sdf_spread > 0 {
+			self.sdf_spread
 		} else {
-			//warn!("Unknown section: {}", self.section_type_as_str()?);
+			self.outline
 		}
-		Ok(())
 	}
 
-	fn read_char_def(&self) -> Result<PF2CharDef, Error> {
-		let mut cursor = self.cursor.borrow_mut();
-		let width = cursor.read_u16::<BigEndian>()?;
-		let height = cursor.read_u16::<BigEndian>()?;
-		let x_offset = cursor.read_i16::<BigEndian>()?;
-		let y_offset = cursor.read_i16::<BigEndian>()?;
-		let device_width = cursor.read_i16::<BigEndian>()?;
-		Ok(PF2CharDef { width, height, x_offset, y_offset, device_width })
+	fn scaled_char_size(&self, def: &PF2CharDef) -> (usize, usize) {
+		(
+			(def.width as usize).div_ceil(self.supersampling) + 2 * self.margin(),
+			(def.height as usize).div_ceil(self.supersampling) + 2 * self.margin()
+		)
 	}
 
-	fn parse_char_bitmap(&self, index: usize, def: &PF2CharDef) -> FontGlyph {
-		let x0 = (index % self.col_count) * self.max_width as usize;
-		let y0 = (index / self.col_count) * self.max_height as usize;
-		let mut texture_data = self.texture_data.borrow_mut();
-		let base = self.cursor.borrow().position() as usize;
-		for y in 0..def.height as usize {
-			let j = (y0 + y) * self.texture_width + x0;
-			for x in 0..def.width as usize {
-				let i = y * def.width as usize + x;
-				let byte = self.data[base + i / 8];
-				if byte & (1 << (7 - i % 8)) != 0 {
-					texture_data[j + x] = RGBA8::new(255, 255, 255, 255);
+	fn rasterize_char_bitmap<P: AtlasPixel>(&self, def: &PF2CharDef, bitmap: &[u8], dest: &mut [P], stride: usize) {
+		if self.sdf_spread > 0 {
+			self.rasterize_sdf_char_bitmap(def, bitmap, dest, stride);
+			return;
+		}
+		if self.outline > 0 {
+			self.rasterize_outlined_char_bitmap(def, bitmap, dest, stride);
+			return;
+		}
+		let factor = self.supersampling;
+		if factor == 1 {
+			self.rasterize_unscaled_char_bitmap(def, bitmap, dest, stride);
+			return;
+		}
+		let (width, height) = self.scaled_char_size(def);
+		let bit = |x: usize, y: usize| {
+			let i = y * def.width as usize + x;
+			bitmap[i / 8] & (1 << (7 - i % 8)) != 0
+		};
+		for y in 0..height {
+			let j = y * stride;
+			let sy_range = y * factor..((y + 1) * factor).min(def.height as usize);
+			for x in 0..width {
+				let sx_range = x * factor..((x + 1) * factor).min(def.width as usize);
+				let count = sy_range.clone()
+					.flat_map(|sy| sx_range.clone().filter(move |&sx| bit(sx, sy)))
+					.count();
+				if count != 0 {
+					let alpha = (count * 255 / (factor * factor)) as u8;
+					dest[j + x] = P::from_coverage(alpha, self.foreground, self.background);
 				}
 			}
 		}
-		FontGlyph { 
-			tex_coord: Vec2::new(x0 as f32 / self.texture_width as f32, y0 as f32 / self.texture_height as f32), 
-			tex_size: Vec2::new(def.width as f32 / self.texture_width as f32, def.height as f32 / self.texture_height as f32), 
-			offset: Vec2::new(def.x_offset as f32 / self.point_size as f32, def.y_offset as f32 / self.point_size as f32), 
-			size: Vec2::new(def.width as f32 / self.point_size as f32, def.height as f32 / self.point_size as f32), 
-			width: def.device_width as f32 / self.point_size as f32
-		}
 	}
 
-	fn parse_data_section(&mut self) -> Result<(), Error> {
-		if self.character_index.is_empty() {
-			return Err(Error::new(ErrorKind::Other, "Character index is empty"));
-		}
-		if self.max_width == 0 {
-			return Err(Error::new(ErrorKind::Other, "Max width is unspecified or zero"));
+	/// One texel per bitmap pixel. Rows aren't byte-aligned in PFF2, so the bitmap is walked
+	/// a byte at a time while tracking the destination position, skipping empty bytes whole
+	/// and writing full bytes within a row as one 8 texel `fill`.
+	fn rasterize_unscaled_char_bitmap<P: AtlasPixel>(&self, def: &PF2CharDef, bitmap: &[u8], dest: &mut [P], stride: usize) {
+		let (width, height) = (def.width as usize, def.height as usize);
+		if width == 0 || height == 0 {
+			return;
 		}
-		if self.max_height == 0 {
-			return Err(Error::new(ErrorKind::Other, "Max height is unspecified or zero"));
+		let pixel_count = width * height;
+		debug_assert!(bitmap.len() >= pixel_count.div_ceil(8));
+		debug_assert!(dest.len() >= (height - 1) * stride + width);
+		let solid = P::from_coverage(255, self.foreground, self.background);
+		let (mut x, mut row) = (0, 0);
+		for (i, &byte) in bitmap[..pixel_count.div_ceil(8)].iter().enumerate() {
+			// The last byte may hold up to 7 padding bits past the glyph.
+			let bits = (pixel_count - i * 8).min(8);
+			if byte == 0 {
+				x += bits;
+				while x >= width {
+					x -= width;
+					row += stride;
+				}
+				continue;
+			}
+			if byte == 0xFF && x + 8 <= width {
+				dest[row + x..row + x + 8].fill(solid);
+				x += 8;
+				if x == width {
+					x = 0;
+					row += stride;
+				}
+				continue;
+			}
+			for bit in 0..bits {
+				if byte & (0x80 >> bit) != 0 {
+					dest[row + x] = solid;
+				}
+				x += 1;
+				if x == width {
+					x = 0;
+					row += stride;
+				}
+			}
 		}
-		self.col_count = (self.character_index.len() as f32 * self.max_height as f32 / self.max_width as f32).sqrt().ceil() as usize;
-		self.texture_width = self.col_count * self.max_width as usize;
-		self.texture_height = (self.character_index.len() + self.col_count - 1) / self.col_count * self.max_height as usize;
-		self.texture_data.get_mut().resize(self.texture_width * self.texture_height, RGBA8::default());
-		self.glyphs.reserve(self.character_index.len());
-		for (unicode_code_point, (offset, index)) in &self.character_index {
-			self.cursor.get_mut().set_position(*offset as u64);
-			let def = self.read_char_def()?;
-			let glyph = self.parse_char_bitmap(*index, &def);
-			self.glyphs.insert(*unicode_code_point, glyph);
+	}
+
+	/// Rasterizes the fill into a scratch buffer, then takes the largest fill coverage within
+	/// `outline` texels of every texel as its outline coverage.
+	fn rasterize_outlined_char_bitmap<P: AtlasPixel>(&self, def: &PF2CharDef, bitmap: &[u8], dest: &mut [P], stride: usize) {
+		let fill_rasterizer = GlyphRasterizer { outline: 0, ..*self };
+		let (fill_width, fill_height) = fill_rasterizer.scaled_char_size(def);
+		let mut fill = vec![0u8; fill_width * fill_height];
+		fill_rasterizer.rasterize_char_bitmap(def, bitmap, &mut fill, fill_width);
+		let fill_at = |x: isize, y: isize| {
+			if x < 0 || y < 0 || x as usize >= fill_width || y as usize >= fill_height {
+				0
+			} else {
+				fill[y as usize * fill_width + x as usize]
+			}
+		};
+		let radius = self.outline as isize;
+		let (width, height) = self.scaled_char_size(def);
+		for y in 0..height {
+			let fy = y as isize - radius;
+			for x in 0..width {
+				let fx = x as isize - radius;
+				let mut outline = 0;
+				for dy in -radius..=radius {
+					for dx in -radius..=radius {
+						if dx * dx + dy * dy <= radius * radius {
+							outline = outline.max(fill_at(fx + dx, fy + dy));
+						}
+					}
+				}
+				if outline != 0 {
+					dest[y * stride + x] = P::from_outlined(fill_at(fx, fy), outline);
+				}
+			}
 		}
-		Ok(())
 	}
 
-	pub fn load(&mut self) -> Result<(Vec<RGBA8>, HashMap<u32, FontGlyph>), Error> {
-		self.read_section()?;
-		if self.section_type != Self::make_section_type(b"FILE") {
-			return Err(Error::new(ErrorKind::Other, format!("Expected \"FILE\" section, but \"{}\" found", self.section_type_as_str()?)));
-		}
-		if self.section_as_str()? != "PFF2" {
-			return Err(Error::new(
-				ErrorKind::Other, 
-				format!(
-					"FILE section contents must be equal to \"PFF2\", but \"{}\" found", 
-					self.section_as_str()?
-				)
-			));
+	/// Computes the signed distance from every glyph pixel to the nearest edge with 8SSEDT at
+	/// the bitmap resolution, then averages it over each `supersampling`×`supersampling` block.
+	/// Distances of `±sdf_spread` texels map to 255 inside and 0 outside, the edge is at 127.5.
+	fn rasterize_sdf_char_bitmap<P: AtlasPixel>(&self, def: &PF2CharDef, bitmap: &[u8], dest: &mut [P], stride: usize) {
+		let factor = self.supersampling;
+		let margin = self.sdf_spread * factor;
+		let (width, height) = self.scaled_char_size(def);
+		let (field_width, field_height) = (width * factor, height * factor);
+		let ink = |x: usize, y: usize| {
+			if x < margin || y < margin || x - margin >= def.width as usize || y - margin >= def.height as usize {
+				return false;
+			}
+			let i = (y - margin) * def.width as usize + (x - margin);
+			bitmap[i / 8] & (1 << (7 - i % 8)) != 0
+		};
+		let mut to_ink = Vec::with_capacity(field_width * field_height);
+		let mut to_background = Vec::with_capacity(field_width * field_height);
+		for y in 0..field_height {
+			for x in 0..field_width {
+				let inside = ink(x, y);
+				to_ink.push(if inside { (0, 0) } else { FAR_POINT });
+				to_background.push(if inside { FAR_POINT } else { (0, 0) });
+			}
 		}
-		while self.read_section()? {
-			self.parse_section()?;
+		distance_transform(&mut to_ink, field_width, field_height);
+		distance_transform(&mut to_background, field_width, field_height);
+
+		let scale = 127.5 / (self.sdf_spread * factor) as f32;
+		for y in 0..height {
+			for x in 0..width {
+				let mut sum = 0.0;
+				for sy in y * factor..(y + 1) * factor {
+					for sx in x * factor..(x + 1) * factor {
+						let i = sy * field_width + sx;
+						// Pixel centers are half a pixel away from the edge between them.
+						sum += if ink(sx, sy) {
+							offset_length(to_background[i]) - 0.5
+						} else {
+							0.5 - offset_length(to_ink[i])
+						};
+					}
+				}
+				let distance = sum / (factor * factor) as f32;
+				let value = (127.5 + distance * scale).clamp(0.0, 255.0) as u8;
+				dest[y * stride + x] = P::from_distance(value, self.foreground);
+			}
 		}
-		if self.section_type != Self::make_section_type(b"DATA") {
-			return Err(Error::new(ErrorKind::Other, format!("Expected \"DATA\" section, but \"{}\" found", self.section_type_as_str()?)));
+	}
+
+	fn parse_char_bitmap<P: AtlasPixel>(&self, (x0, y0): (usize, usize), def: &PF2CharDef, bitmap: &[u8], band: &mut [P], band_y: usize) -> FontGlyph {
+		self.rasterize_char_bitmap(def, bitmap, &mut band[(y0 - band_y) * self.texture_width + x0..], self.texture_width);
+		self.make_atlas_glyph((x0, y0), def)
+	}
+
+	fn make_atlas_glyph(&self, position: (usize, usize), def: &PF2CharDef) -> FontGlyph {
+		self.make_glyph(def, position, (self.texture_width, self.texture_height))
+	}
+
+	fn make_glyph(&self, def: &PF2CharDef, (x0, y0): (usize, usize), (texture_width, texture_height): (usize, usize)) -> FontGlyph {
+		let (width, height) = self.scaled_char_size(def);
+		// The margin is measured in atlas texels, which are `supersampling` glyph pixels wide.
+		let margin = (self.margin() * self.supersampling) as f32;
+		FontGlyph { 
+			tex_coord: Vec2::new(x0 as f32 / texture_width as f32, y0 as f32 / texture_height as f32), 
+			tex_size: Vec2::new(width as f32 / texture_width as f32, height as f32 / texture_height as f32), 
+			tex_rect: (x0 as u32, y0 as u32, width as u32, height as u32),
+			offset: Vec2::new(
+				(def.x_offset as f32 - margin) / self.point_size as f32,
+				(def.y_offset as f32 - margin) / self.point_size as f32
+			), 
+			size: Vec2::new(
+				(def.width as f32 + 2.0 * margin) / self.point_size as f32,
+				(def.height as f32 + 2.0 * margin) / self.point_size as f32
+			), 
+			width: def.device_width as f32 / self.point_size as f32,
+			advance_px: def.device_width as f32
 		}
-		self.parse_data_section()?;
-        let mut texture_data = Vec::<RGBA8>::new();
-        std::mem::swap(self.texture_data.get_mut(), &mut texture_data);
-		let mut glyphs = HashMap::<u32, FontGlyph>::new();
-		std::mem::swap(&mut self.glyphs, &mut glyphs);
-		Ok((texture_data, glyphs))
+	}
+}
+
+impl<'a> PF2Loader<SliceReader<'a>> {
+	pub fn new(data: &'a [u8]) -> Self {
+		Self::from_reader(SliceReader::new(data))
+	}
+}
+
+impl<R: PF2Source> PF2Loader<R> {
+	/// Loads the font from any seekable stream. Section and glyph data is read
+	/// into small temporary buffers, so the whole file is never held in memory.
+	pub fn from_reader(reader: R) -> Self {
+		PF2Loader { 
+			reader: RefCell::new(reader),
+			section_type: 0,
+			section_len: 0,
+			section_start: 0,
+			stream_len: 0,
+			sections_seen: Vec::new(),
+			name: None,
+			family: None,
+			weight: None,
+			slant: None,
+			point_size: 0,
+			max_width: 0,
+			max_height: 0,
+			ascent: 0,
+			descent: 0,
+			headers_parsed: false,
+			strict: false,
+			byte_order: None,
+			file_byte_order: ByteOrder::BigEndian,
+			character_index: HashMap::new(),
+			supersampling: 1,
+			padding: 0,
+			packing: AtlasPacking::Grid,
+			max_texture_dimension: None,
+			pow2_dimensions: false,
+			trim_rows: false,
+			codepoint_filter: None,
+			outline: 0,
+			sdf_spread: 0,
+			foreground: RGBA8::new(255, 255, 255, 255),
+			background: RGBA8::new(0, 0, 0, 0),
+			cell_width: 0,
+			cell_height: 0,
+			col_count: 0,
+			texture_width: 0,
+			texture_height: 0,
+			glyphs: HashMap::new(),
+			timings: LoadTimings::default()
+		}
+	}
+
+	/// Switches the loader to another font, dropping everything parsed from the current one.
+	/// Options set with the `with_*` methods are kept.
+	pub fn reset(&mut self, reader: R) {
+		let loader = PF2Loader::from_reader(reader);
+		*self = PF2Loader {
+			supersampling: self.supersampling,
+			padding: self.padding,
+			packing: self.packing,
+			max_texture_dimension: self.max_texture_dimension,
+			pow2_dimensions: self.pow2_dimensions,
+			trim_rows: self.trim_rows,
+			codepoint_filter: self.codepoint_filter,
+			outline: self.outline,
+			sdf_spread: self.sdf_spread,
+			foreground: self.foreground,
+			background: self.background,
+			strict: self.strict,
+			byte_order: self.byte_order,
+			..loader
+		};
+	}
+
+	/// Treats glyph bitmaps as rendered at `factor` times the atlas resolution and
+	/// box-filters each `factor`×`factor` block into one texel with a fractional alpha.
+	/// Glyph edges which don't fill a whole block count the missing samples as empty.
+	/// A factor of 0 is treated as 1.
+	pub fn with_supersampling(mut self, factor: u32) -> Self {
+		self.supersampling = factor.max(1) as usize;
+		self
+	}
+
+	/// Leaves `padding` transparent texels between neighbouring atlas cells, so
+	/// linear filtering doesn't bleed one glyph into another. One texel is usually enough.
+	pub fn with_padding(mut self, padding: usize) -> Self {
+		self.padding = padding;
+		self
+	}
+
+	pub fn with_packing(mut self, packing: AtlasPacking) -> Self {
+		self.packing = packing;
+		self
+	}
+
+	/// Makes `parse_headers()` fail with `PF2Error::UnknownSection` on sections the loader
+	/// doesn't know instead of skipping them, e.g. to catch files from newer PFF2 versions.
+	/// Loading also checks that the data of every glyph lies within DATA without overlapping
+	/// another glyph's, failing with `PF2Error::OverlappingGlyphData` before reading bogus
+	/// bitmaps from a broken character index.
+	pub fn with_strict(mut self, strict: bool) -> Self {
+		self.strict = strict;
+		self
+	}
+
+	/// Reads the file with the given byte order instead of detecting it, failing with
+	/// `PF2Error::WrongByteOrder` if the file clearly uses the other one.
+	pub fn with_byte_order(mut self, byte_order: ByteOrder) -> Self {
+		self.byte_order = Some(byte_order);
+		self
+	}
+
+	/// Colors of RGBA atlases: glyph coverage blends `foreground` over `background`, which
+	/// also fills everything between glyphs. The default is white on transparent black.
+	/// Outlined and grayscale atlases ignore them.
+	pub fn with_colors(mut self, foreground: RGBA8, background: RGBA8) -> Self {
+		self.foreground = foreground;
+		self.background = background;
+		self
+	}
+
+	/// Same as `with_colors`, with the colors of a common atlas layout.
+	pub fn with_layout(self, layout: AtlasLayout) -> Self {
+		let (foreground, background) = layout.colors();
+		self.with_colors(foreground, background)
+	}
+
+	/// Bakes an outline `radius` atlas texels wide around every glyph. RGBA atlases keep the
+	/// fill coverage in the color channels and the coverage of fill plus outline in alpha, so
+	/// a shader can tell white fill from black outline texels; grayscale atlases only keep the
+	/// fill. Every glyph, and so every grid cell, grows by `2 * radius` texels in both
+	/// directions, and glyph offsets and sizes include the outline.
+	pub fn with_outline(mut self, radius: usize) -> Self {
+		self.outline = radius;
+		self
+	}
+
+	/// Stores a signed distance field instead of coverage: 255 is `spread` texels or more
+	/// inside the glyph, 0 is `spread` texels or more outside and the edge lies at 127.5, so
+	/// a shader can threshold it at 0.5 for crisp edges at any scale. The distance is in the
+	/// alpha channel of RGBA atlases, whose color is the foreground. Glyphs grow by `spread`
+	/// texels on every side like they do with an outline, which is ignored. 0 disables it.
+	pub fn with_sdf(mut self, spread: usize) -> Self {
+		self.sdf_spread = spread;
+		self
+	}
+
+	/// Keeps both atlas dimensions at or below `max_dimension` texels, e.g. the GPU's
+	/// maximum texture size, by reshaping the layout. Loading fails with
+	/// `PF2Error::AtlasTooLarge` if the glyphs can't fit at all.
+	pub fn with_max_texture_dimension(mut self, max_dimension: usize) -> Self {
+		self.max_texture_dimension = Some(max_dimension);
+		self
+	}
+
+	/// Rounds both atlas dimensions up to the next power of two for hardware that needs it.
+	/// The extra texels are filled with the background and texture coordinates refer to the
+	/// rounded size. The rounded size must still fit `with_max_texture_dimension`.
+	pub fn with_pow2_dimensions(mut self, pow2_dimensions: bool) -> Self {
+		self.pow2_dimensions = pow2_dimensions;
+		self
+	}
+
+	/// Cuts the atlas off below the lowest glyph instead of at the bottom of its last grid
+	/// row, which is often mostly empty. Texture coordinates refer to the trimmed height, and
+	/// empty glyphs on rows below it (e.g. spaces) get coordinates on the bottom edge.
+	pub fn with_trim_rows(mut self, trim_rows: bool) -> Self {
+		self.trim_rows = trim_rows;
+		self
+	}
+
+	/// Only rasterizes and packs the glyphs whose code point `filter` accepts, e.g.
+	/// `|cp| (0x20..0x7F).contains(&cp)` for printable ASCII. The character index itself
+	/// is still read in full.
+	pub fn with_codepoint_filter(mut self, filter: fn(u32) -> bool) -> Self {
+		self.codepoint_filter = Some(filter);
+		self
+	}
+
+	fn read_section(&mut self) -> Result<bool, PF2Error> {
+		let reader = self.reader.get_mut();
+		reader.seek((self.section_start + self.section_len) as u64)?;
+		let (tag, start, len) = read_section_header(reader, self.file_byte_order, self.stream_len)?;
+		self.section_type = u32::from_be_bytes(tag);
+		self.section_start = start as usize;
+		self.section_len = len.unwrap_or(0xFFFFFFFF);
+		self.sections_seen.push(tag);
+		Ok(len.is_some())
+	}
+
+	fn read_section_data(&mut self) -> Result<Vec<u8>, PF2Error> {
+		let mut data = vec![0; self.section_len];
+		self.reader.get_mut().read_exact(&mut data)?;
+		Ok(data)
+	}
+
+	fn read_section_string(&mut self) -> Result<Option<String>, PF2Error> {
+		let data = self.read_section_data()?;
+		Ok(String::from_utf8(data).ok()
+			.map(|s| s.trim_end_matches('\0').to_owned())
+			.filter(|s| !s.is_empty()))
+	}
+
+	fn parse_character_index(&mut self) -> Result<(), PF2Error> {
+		if !self.character_index.is_empty() {
+			return Err(PF2Error::DuplicateCharacterIndex);
+		}
+		const RECORD_LEN: usize = size_of::<u32>() + size_of::<u8>() + size_of::<u32>();
+		if !self.section_len.is_multiple_of(RECORD_LEN) {
+			return Err(PF2Error::InvalidCharacterIndex);
+		}
+		let count = self.section_len / RECORD_LEN;
+		self.character_index.reserve(count);
+		let reader = self.reader.get_mut();
+		for _ in 0..count {
+			let unicode_code_point = read_u32(reader, self.file_byte_order)?;
+			let flags = read_u8(reader)?;
+			let offset = read_u32(reader, self.file_byte_order)?;
+			// Only uncompressed glyphs (storage flags 000) are supported. Value 001 marks
+			// a compressed character definition block, but the PFF2 spec never defined its
+			// format. The remaining bits describe glyph joining and are ignored.
+			if flags & 0b111 != 0 {
+				return Err(PF2Error::UnsupportedStorageFlags { code_point: unicode_code_point, flags: flags & 0b111 });
+			}
+			// A code point listed twice has no meaningful answer for which definition wins,
+			// so such an index is rejected instead of silently keeping one of them.
+			let index = self.character_index.len();
+			if self.character_index.insert(unicode_code_point, (offset, index)).is_some() {
+				return Err(PF2Error::DuplicateCodePoint(unicode_code_point));
+			}
+		}
+		trace!("Character index contains {} items", self.character_index.len());
+		Ok(())
+	}
+
+	fn parse_section(&mut self) -> Result<(), PF2Error> {
+		let reader = self.reader.get_mut();
+		match self.section_type {
+			section::NAME => {
+				self.name = self.read_section_string()?;
+				trace!("Font name: {:?}", self.name);
+			}
+			section::FAMI => {
+				self.family = self.read_section_string()?;
+				trace!("Font family: {:?}", self.family);
+			}
+			section::WEIG => {
+				self.weight = self.read_section_string()?;
+				trace!("Font weight: {:?}", self.weight);
+			}
+			section::SLAN => {
+				self.slant = self.read_section_string()?;
+				trace!("Font slant: {:?}", self.slant);
+			}
+			section::PTSZ => {
+				let point_size = read_u16(reader, self.file_byte_order)?;
+				// A PFF2 file holds a single size, packs of several sizes are one file per size.
+				// Glyphs can't be told apart by size, so a second size can't be honoured.
+				if self.point_size != 0 && self.point_size != point_size {
+					return Err(PF2Error::MultiplePointSizes { first: self.point_size, second: point_size });
+				}
+				self.point_size = point_size;
+				trace!("Point size: {}", self.point_size);
+			}
+			section::MAXW => {
+				self.max_width = read_u16(reader, self.file_byte_order)?;
+				trace!("Max width: {}", self.max_width);
+			}
+			section::MAXH => {
+				self.max_height = read_u16(reader, self.file_byte_order)?;
+				trace!("Max height: {}", self.max_height);
+			}
+			section::ASCE => {
+				self.ascent = read_u16(reader, self.file_byte_order)?;
+				trace!("Ascent: {}", self.ascent);
+			}
+			section::DESC => {
+				self.descent = read_u16(reader, self.file_byte_order)?;
+				trace!("Descent: {}", self.descent);
+			}
+			section::CHIX => {
+				self.parse_character_index()?;
+			}
+			_ => {
+				if self.strict {
+					return Err(PF2Error::UnknownSection(self.section_type.to_be_bytes()));
+				}
+				warn!("Unknown section: {}", String::from_utf8_lossy(&self.section_type.to_be_bytes()));
+			}
+		}
+		Ok(())
+	}
+
+	fn read_char_def(&self) -> Result<PF2CharDef, PF2Error> {
+		let byte_order = self.file_byte_order;
+		let reader = &mut *self.reader.borrow_mut();
+		let width = read_u16(reader, byte_order)?;
+		let height = read_u16(reader, byte_order)?;
+		let x_offset = read_i16(reader, byte_order)?;
+		let y_offset = read_i16(reader, byte_order)?;
+		let device_width = read_i16(reader, byte_order)?;
+		Ok(PF2CharDef { width, height, x_offset, y_offset, device_width })
+	}
+
+	fn read_char_bitmap(&self, code_point: u32, def: &PF2CharDef, bitmap: &mut Vec<u8>) -> Result<(), PF2Error> {
+		let len = (def.width as usize * def.height as usize).div_ceil(8);
+		let reader = &mut *self.reader.borrow_mut();
+		// A bogus size would otherwise allocate up to 512 MB before the read fails.
+		if reader.position()? + len as u64 > self.stream_len {
+			return Err(PF2Error::UnexpectedEof);
+		}
+		bitmap.try_reserve_exact(len).map_err(|_| PF2Error::GlyphTooLarge(code_point))?;
+		let start = bitmap.len();
+		bitmap.resize(start + len, 0);
+		reader.read_exact(&mut bitmap[start..])?;
+		Ok(())
+	}
+
+	fn rasterizer(&self) -> GlyphRasterizer {
+		GlyphRasterizer {
+			supersampling: self.supersampling,
+			outline: self.outline,
+			sdf_spread: self.sdf_spread,
+			foreground: self.foreground,
+			background: self.background,
+			point_size: self.point_size,
+			texture_width: self.texture_width,
+			texture_height: self.texture_height
+		}
+	}
+
+	fn check_data_section(&self) -> Result<(), PF2Error> {
+		if self.character_index.is_empty() {
+			return Err(PF2Error::MissingCharacterIndex);
+		}
+		if self.max_width == 0 || self.max_height == 0 {
+			return Err(PF2Error::InvalidDimensions);
+		}
+		Ok(())
+	}
+
+	/// Appends the definition of every indexed glyph to `chars` and, if given, its bitmap to `bitmaps`.
+	fn read_chars(&mut self, chars: &mut Vec<PF2CharEntry>, mut bitmaps: Option<&mut Vec<u8>>) -> Result<(), PF2Error> {
+		self.check_data_section()?;
+		chars.reserve(self.character_index.len());
+		// Start, end and code point of every glyph's data, checked for overlaps in strict mode.
+		let mut regions = Vec::new();
+		// `parse_headers()` stops right after the DATA header, which runs to the end of the file.
+		let data_start = self.section_start as u64;
+		for (unicode_code_point, (offset, index)) in &self.character_index {
+			if self.codepoint_filter.is_some_and(|filter| !filter(*unicode_code_point)) {
+				continue;
+			}
+			if self.strict && (*offset as u64 + CHAR_DEF_LEN > self.stream_len || (*offset as u64) < data_start) {
+				return Err(PF2Error::OverlappingGlyphData(*unicode_code_point));
+			}
+			self.reader.borrow_mut().seek(*offset as u64)?;
+			let def = self.read_char_def()?;
+			if def.width > self.max_width || def.height > self.max_height {
+				return Err(PF2Error::GlyphTooLarge(*unicode_code_point));
+			}
+			if self.strict {
+				let end = *offset as u64 + CHAR_DEF_LEN + (def.width as u64 * def.height as u64).div_ceil(8);
+				if end > self.stream_len {
+					return Err(PF2Error::OverlappingGlyphData(*unicode_code_point));
+				}
+				regions.push((*offset as u64, end, *unicode_code_point));
+			}
+			let bitmap_offset = self.reader.borrow_mut().position()?;
+			let bitmap = match bitmaps.as_deref_mut() {
+				Some(bitmaps) => {
+					let bitmap_start = bitmaps.len();
+					self.read_char_bitmap(*unicode_code_point, &def, bitmaps)?;
+					bitmap_start..bitmaps.len()
+				}
+				None => 0..0
+			};
+			chars.push(PF2CharEntry {
+				code_point: *unicode_code_point,
+				index: *index,
+				point_size: self.point_size,
+				bitmap_offset,
+				bitmap,
+				def
+			});
+		}
+		regions.sort_unstable();
+		if let Some(pair) = regions.windows(2).find(|pair| pair[0].1 > pair[1].0) {
+			return Err(PF2Error::OverlappingGlyphData(pair[1].2));
+		}
+		Ok(())
+	}
+
+	fn parse_data_section<P: AtlasPixel>(&mut self, texture_data: &mut Vec<P>) -> Result<(), PF2Error> {
+		let mut chars = Vec::new();
+		let mut bitmaps = Vec::new();
+		let mut stopwatch = Stopwatch::start();
+		self.read_chars(&mut chars, Some(&mut bitmaps))?;
+		self.push_notdef_box(&mut chars, &mut bitmaps);
+		self.timings.read = stopwatch.lap();
+		self.build_atlas(&chars, &bitmaps, texture_data)
+	}
+
+	/// Adds a hollow box half an em wide and `ascent` tall to the atlas if the font has no
+	/// notdef glyph at code point 0, so `LoadedFont::glyph_or_notdef` still draws a tofu box.
+	fn push_notdef_box(&self, chars: &mut Vec<PF2CharEntry>, bitmaps: &mut Vec<u8>) {
+		if chars.iter().any(|entry| entry.code_point == 0 || entry.code_point == NOTDEF_BOX) {
+			return;
+		}
+		let device_width = (self.point_size / 2).max(4);
+		let (width, height) = (device_width - 2, self.ascent.max(3));
+		let bitmap_start = bitmaps.len();
+		bitmaps.resize(bitmap_start + (width as usize * height as usize).div_ceil(8), 0);
+		for y in 0..height {
+			for x in 0..width {
+				if y == 0 || y == height - 1 || x == 0 || x == width - 1 {
+					let bit = y as usize * width as usize + x as usize;
+					bitmaps[bitmap_start + bit / 8] |= 0x80 >> (bit % 8);
+				}
+			}
+		}
+		chars.push(PF2CharEntry {
+			code_point: NOTDEF_BOX,
+			// Grid layout orders glyphs by index, so the box goes last.
+			index: usize::MAX,
+			point_size: self.point_size,
+			bitmap_offset: 0,
+			bitmap: bitmap_start..bitmaps.len(),
+			def: PF2CharDef { width, height, x_offset: 1, y_offset: 0, device_width: device_width as i16 }
+		});
+	}
+
+	fn build_atlas<P: AtlasPixel>(&mut self, chars: &[PF2CharEntry], bitmaps: &[u8], texture_data: &mut Vec<P>) -> Result<(), PF2Error> {
+		let mut stopwatch = Stopwatch::start();
+		let positions = self.layout(chars)?;
+		self.timings.layout = stopwatch.lap();
+		// Reports the failed size instead of aborting the process on a hopeless allocation.
+		let dimension = self.texture_width.max(self.texture_height);
+		let len = self.texture_width.checked_mul(self.texture_height).ok_or(PF2Error::AtlasTooLarge(dimension))?;
+		texture_data.try_reserve_exact(len.saturating_sub(texture_data.len()))
+			.map_err(|_| PF2Error::AtlasTooLarge(dimension))?;
+		texture_data.resize(len, P::background(self.background));
+		self.timings.allocation = stopwatch.lap();
+		let rasterizer = self.rasterizer();
+		// Empty glyphs have no texels to write, and with trimmed rows they can even sit on
+		// the bottom edge of the atlas, outside of any band.
+		let (mut order, empty): (Vec<_>, Vec<_>) = (0..chars.len())
+			.partition(|&i| !matches!(rasterizer.scaled_char_size(&chars[i].def), (0, _) | (_, 0)));
+		self.glyphs.extend(empty.iter().map(|&i| {
+			let rasterizer = GlyphRasterizer { point_size: chars[i].point_size, ..rasterizer };
+			(chars[i].code_point, rasterizer.make_atlas_glyph(positions[i], &chars[i].def))
+		}));
+		// Glyphs starting at the same row never reach the next such row, so splitting
+		// the atlas at those rows gives every group its own disjoint band to write to.
+		order.sort_by_key(|&i| positions[i].1);
+		let mut bands = Vec::new();
+		let mut rest = texture_data.as_mut_slice();
+		for group in order.chunk_by(|&a, &b| positions[a].1 == positions[b].1).rev() {
+			let band_y = positions[group[0]].1;
+			let (head, band) = rest.split_at_mut(band_y * self.texture_width);
+			rest = head;
+			bands.push((band_y, group, band));
+		}
+		let rasterize_band = |(band_y, group, band): (usize, &[usize], &mut [P])| {
+			group.iter().map(|&i| {
+				let entry = &chars[i];
+				let rasterizer = GlyphRasterizer { point_size: entry.point_size, ..rasterizer };
+				let glyph = rasterizer.parse_char_bitmap(positions[i], &entry.def, &bitmaps[entry.bitmap.clone()], band, band_y);
+				(entry.code_point, glyph)
+			}).collect::<Vec<_>>()
+		};
+		#[cfg(feature = "rayon")]
+		let glyphs: Vec<_> = bands.into_par_iter().flat_map_iter(rasterize_band).collect();
+		#[cfg(not(feature = "rayon"))]
+		let glyphs: Vec<_> = bands.into_iter().flat_map(rasterize_band).collect();
+		self.glyphs.reserve(glyphs.len());
+		self.glyphs.extend(glyphs);
+		self.timings.rasterization = stopwatch.lap();
+		Ok(())
+	}
+
+	fn layout(&mut self, chars: &[PF2CharEntry]) -> Result<Vec<(usize, usize)>, PF2Error> {
+		if chars.is_empty() {
+			// Everything was filtered out, which leaves an empty atlas.
+			self.texture_width = 0;
+			self.texture_height = 0;
+		}
+		let mut positions = match self.packing {
+			_ if chars.is_empty() => Vec::new(),
+			AtlasPacking::Grid => self.layout_grid(chars)?,
+			AtlasPacking::Shelf => self.layout_shelves(chars)?
+		};
+		if self.trim_rows {
+			let rasterizer = self.rasterizer();
+			self.texture_height = chars.iter().zip(&positions)
+				.map(|(entry, &(_, y))| (rasterizer.scaled_char_size(&entry.def), y))
+				.filter(|&((width, height), _)| width > 0 && height > 0)
+				.map(|((_, height), y)| y + height)
+				.max()
+				.unwrap_or(0)
+				.max(1);
+			// Trailing rows of empty glyphs are cut off, so keep their texture coordinates
+			// on the atlas edge instead.
+			for position in &mut positions {
+				position.1 = position.1.min(self.texture_height);
+			}
+		}
+		if self.pow2_dimensions {
+			// Texture coordinates are computed against these dimensions after the layout,
+			// so glyphs only have to be placed before rounding them up.
+			self.texture_width = self.texture_width.next_power_of_two();
+			self.texture_height = self.texture_height.next_power_of_two();
+			if let Some(max_dimension) = self.max_texture_dimension {
+				if self.texture_width > max_dimension || self.texture_height > max_dimension {
+					return Err(PF2Error::AtlasTooLarge(max_dimension));
+				}
+			}
+		}
+		Ok(positions)
+	}
+
+	fn layout_grid(&mut self, chars: &[PF2CharEntry]) -> Result<Vec<(usize, usize)>, PF2Error> {
+		// Cells fit the largest glyph actually read rather than MAXW and MAXH, which a broken
+		// font can declare far larger than any of its glyphs. Fonts of only empty glyphs (e.g.
+		// spaces) still get one texel per cell, so the column count stays meaningful.
+		let max_width = chars.iter().map(|entry| entry.def.width).max().unwrap_or(0) as usize;
+		let max_height = chars.iter().map(|entry| entry.def.height).max().unwrap_or(0) as usize;
+		self.cell_width = (max_width.div_ceil(self.supersampling) + 2 * self.rasterizer().margin()).max(1) + self.padding;
+		self.cell_height = (max_height.div_ceil(self.supersampling) + 2 * self.rasterizer().margin()).max(1) + self.padding;
+		self.col_count = (chars.len() as f32 * self.cell_height as f32 / self.cell_width as f32).sqrt().ceil() as usize;
+		if let Some(max_dimension) = self.max_texture_dimension {
+			let max_cols = (max_dimension + self.padding) / self.cell_width;
+			let max_rows = (max_dimension + self.padding) / self.cell_height;
+			if max_cols == 0 || max_rows == 0 {
+				return Err(PF2Error::AtlasTooLarge(max_dimension));
+			}
+			self.col_count = self.col_count.min(max_cols);
+			if chars.len().div_ceil(self.col_count) > max_rows {
+				self.col_count = chars.len().div_ceil(max_rows);
+				if self.col_count > max_cols {
+					return Err(PF2Error::AtlasTooLarge(max_dimension));
+				}
+			}
+		}
+		self.texture_width = self.col_count * self.cell_width - self.padding;
+		self.texture_height = chars.len().div_ceil(self.col_count) * self.cell_height - self.padding;
+		// Cells follow the character index order, skipping glyphs left out by the filter.
+		let mut order: Vec<_> = (0..chars.len()).collect();
+		order.sort_by_key(|&i| chars[i].index);
+		let mut positions = vec![(0, 0); chars.len()];
+		for (cell, &i) in order.iter().enumerate() {
+			positions[i] = ((cell % self.col_count) * self.cell_width, (cell / self.col_count) * self.cell_height);
+		}
+		Ok(positions)
+	}
+
+	fn layout_shelves(&mut self, chars: &[PF2CharEntry]) -> Result<Vec<(usize, usize)>, PF2Error> {
+		let rasterizer = self.rasterizer();
+		let sizes: Vec<_> = chars.iter().map(|entry| rasterizer.scaled_char_size(&entry.def)).collect();
+		let area: usize = sizes.iter().map(|(w, h)| (w + self.padding) * (h + self.padding)).sum();
+		let max_width = sizes.iter().map(|(w, _)| *w).max().unwrap_or(0);
+		let mut target_width = ((area as f32).sqrt().ceil() as usize).max(max_width).max(1);
+		if let Some(max_dimension) = self.max_texture_dimension {
+			if max_width > max_dimension {
+				return Err(PF2Error::AtlasTooLarge(max_dimension));
+			}
+			target_width = target_width.min(max_dimension);
+		}
+		let mut order: Vec<_> = (0..chars.len()).collect();
+		order.sort_by_key(|&i| (core::cmp::Reverse(sizes[i].1), chars[i].code_point));
+		let mut positions = self.pack_shelves(&sizes, &order, target_width);
+		if let Some(max_dimension) = self.max_texture_dimension {
+			// Too tall for the limit: use the widest allowed shelves before giving up.
+			if self.texture_height > max_dimension && target_width < max_dimension {
+				positions = self.pack_shelves(&sizes, &order, max_dimension);
+			}
+			if self.texture_height > max_dimension {
+				return Err(PF2Error::AtlasTooLarge(max_dimension));
+			}
+		}
+		Ok(positions)
+	}
+
+	fn pack_shelves(&mut self, sizes: &[(usize, usize)], order: &[usize], target_width: usize) -> Vec<(usize, usize)> {
+		let mut positions = vec![(0, 0); sizes.len()];
+		let (mut x, mut y, mut shelf_height, mut width) = (0, 0, 0, 0);
+		for &i in order {
+			let (w, h) = sizes[i];
+			if w == 0 || h == 0 {
+				continue;
+			}
+			if x > 0 && x + w > target_width {
+				y += shelf_height + self.padding;
+				x = 0;
+				shelf_height = 0;
+			}
+			positions[i] = (x, y);
+			width = width.max(x + w);
+			shelf_height = shelf_height.max(h);
+			x += w + self.padding;
+		}
+		self.texture_width = width.max(1);
+		self.texture_height = (y + shelf_height).max(1);
+		positions
+	}
+
+	pub fn name(&self) -> Option<&str> {
+		self.name.as_deref()
+	}
+
+	pub fn family(&self) -> Option<&str> {
+		self.family.as_deref()
+	}
+
+	pub fn weight(&self) -> Option<&str> {
+		self.weight.as_deref()
+	}
+
+	pub fn slant(&self) -> Option<&str> {
+		self.slant.as_deref()
+	}
+
+	/// Tags of every section `parse_headers()` read so far, in file order, including
+	/// FILE, DATA and sections the loader doesn't know.
+	pub fn sections_seen(&self) -> &[[u8; 4]] {
+		&self.sections_seen
+	}
+
+	/// Whether the font has a glyph for `code_point`, valid once `parse_headers()` has
+	/// completed. Only the character index is consulted, so no bitmap is read.
+	pub fn contains(&self, code_point: u32) -> bool {
+		self.character_index.contains_key(&code_point)
+	}
+
+	/// Every code point in the character index in file order, valid once
+	/// `parse_headers()` has completed.
+	pub fn available_codepoints(&self) -> impl Iterator<Item = u32> {
+		let mut code_points: Vec<_> = self.character_index.iter()
+			.map(|(&code_point, &(_, index))| (index, code_point))
+			.collect();
+		code_points.sort_unstable();
+		code_points.into_iter().map(|(_, code_point)| code_point)
+	}
+
+	/// The point size from PTSZ, valid once `parse_headers()` has completed. Pick the
+	/// file of the wanted size from a pack with it, since every file holds a single size.
+	pub fn point_size(&self) -> u16 {
+		self.point_size
+	}
+
+	pub fn metrics(&self) -> FontMetrics {
+		let point_size = self.point_size as f32;
+		FontMetrics {
+			ascent: self.ascent as f32 / point_size,
+			descent: self.descent as f32 / point_size,
+			point_size,
+			max_width: self.max_width as f32 / point_size,
+			max_height: self.max_height as f32 / point_size
+		}
+	}
+
+	/// Checks that the file starts with a FILE section before anything trusts its length,
+	/// which is always 4 and so also tells the byte order of the whole file.
+	fn check_file_header(&mut self) -> Result<ByteOrder, PF2Error> {
+		let reader = self.reader.get_mut();
+		reader.seek(0)?;
+		let header: [u8; 8] = read_bytes(reader)?;
+		reader.seek(0)?;
+		let tag = [header[0], header[1], header[2], header[3]];
+		if u32::from_be_bytes(tag) != section::FILE {
+			return Err(PF2Error::UnexpectedSection { expected: *b"FILE", found: tag });
+		}
+		match (self.byte_order, detect_byte_order(&header)) {
+			(Some(expected), Some(found)) if expected != found => Err(PF2Error::WrongByteOrder { expected, found }),
+			(Some(byte_order), _) => Ok(byte_order),
+			(None, detected) => Ok(detected.unwrap_or(ByteOrder::BigEndian))
+		}
+	}
+
+	/// The byte order the file was read with, valid once `parse_headers()` has completed.
+	pub fn byte_order(&self) -> ByteOrder {
+		self.file_byte_order
+	}
+
+	/// Parses every section up to the start of DATA, including the character index,
+	/// without rasterizing anything, e.g. to check `contains` before deciding to use the
+	/// font. `load()` calls it itself if it wasn't called yet.
+	pub fn parse_headers(&mut self) -> Result<(), PF2Error> {
+		if self.headers_parsed {
+			return Ok(());
+		}
+		self.stream_len = self.reader.get_mut().stream_len()?;
+		self.file_byte_order = self.check_file_header()?;
+		self.read_section()?;
+		let magic = self.read_section_data()?;
+		if magic != b"PFF2" {
+			return Err(PF2Error::BadMagic(magic));
+		}
+		while self.read_section()? {
+			self.parse_section()?;
+		}
+		if self.section_type != section::DATA {
+			return Err(PF2Error::UnexpectedSection { expected: *b"DATA", found: self.section_type.to_be_bytes() });
+		}
+		self.headers_parsed = true;
+		Ok(())
+	}
+
+	/// Re-rasterizes one glyph of `font`, which must have been produced by this loader's last
+	/// `load()`, from a new definition and 1 bit per pixel `bitmap` (rows packed without
+	/// padding, most significant bit first), e.g. after editing it. Only the glyph's own grid
+	/// cell, or with shelf packing its old rectangle, is rewritten, so the new glyph must fit
+	/// in there. Returns `false` if `font` has no glyph for the code point, and
+	/// `PF2Error::FontMismatch` if its atlas doesn't match the loader's, e.g. after `reset()`.
+	pub fn update_glyph(&self, font: &mut LoadedFont, code_point: u32, def: PF2CharDef, bitmap: &[u8]) -> Result<bool, PF2Error> {
+		self.update_glyph_pixels(font, code_point, def, bitmap)
+	}
+
+	/// Same as `update_glyph()` for a font from `load_grayscale()`.
+	pub fn update_glyph_grayscale(&self, font: &mut LoadedFont<Vec<u8>>, code_point: u32, def: PF2CharDef, bitmap: &[u8]) -> Result<bool, PF2Error> {
+		self.update_glyph_pixels(font, code_point, def, bitmap)
+	}
+
+	fn update_glyph_pixels<P: AtlasPixel>(&self, font: &mut LoadedFont<Vec<P>>, code_point: u32, def: PF2CharDef, bitmap: &[u8]) -> Result<bool, PF2Error> {
+		let Some(glyph) = font.glyphs.get(&code_point) else {
+			return Ok(false);
+		};
+		// The cell size comes from the loader, so a font from another loader, or one loaded
+		// before `reset`, could have its glyph regions computed wrong.
+		if (self.texture_width, self.texture_height) != (font.texture_width, font.texture_height) || font.texture.len() != font.texture_width * font.texture_height {
+			return Err(PF2Error::FontMismatch);
+		}
+		let (x0, y0, old_width, old_height) = glyph.tex_rect;
+		let (x0, y0) = (x0 as usize, y0 as usize);
+		let (region_width, region_height) = match self.packing {
+			AtlasPacking::Grid => (
+				self.cell_width.checked_sub(self.padding).ok_or(PF2Error::FontMismatch)?,
+				self.cell_height.checked_sub(self.padding).ok_or(PF2Error::FontMismatch)?
+			),
+			AtlasPacking::Shelf => (old_width as usize, old_height as usize)
+		};
+		// Cells in the last row or column may be cut off by `with_trim_rows` or padding.
+		let region_width = region_width.min(font.texture_width.checked_sub(x0).ok_or(PF2Error::FontMismatch)?);
+		let region_height = region_height.min(font.texture_height.checked_sub(y0).ok_or(PF2Error::FontMismatch)?);
+		let rasterizer = GlyphRasterizer {
+			texture_width: font.texture_width,
+			texture_height: font.texture_height,
+			..self.rasterizer()
+		};
+		let (width, height) = rasterizer.scaled_char_size(&def);
+		if width > region_width || height > region_height {
+			return Err(PF2Error::GlyphTooLarge(code_point));
+		}
+		if bitmap.len() < (def.width as usize * def.height as usize).div_ceil(8) {
+			return Err(PF2Error::BitmapTooShort(code_point));
+		}
+		let stride = font.texture_width;
+		let background = P::background(self.background);
+		for y in y0..y0 + region_height {
+			font.texture[y * stride + x0..y * stride + x0 + region_width].fill(background);
+		}
+		rasterizer.rasterize_char_bitmap(&def, bitmap, &mut font.texture[y0 * stride + x0..], stride);
+		font.glyphs.insert(code_point, rasterizer.make_atlas_glyph((x0, y0), &def));
+		Ok(true)
+	}
+
+	/// Rasterizes a single glyph into its own tightly-sized buffer. Fails with
+	/// `PF2Error::HeadersNotParsed` unless `parse_headers()` was called first and returns
+	/// `None` if the font has no glyph for the code point.
+	/// The returned glyph's texture coordinates span the whole buffer.
+	pub fn load_glyph(&self, code_point: u32) -> Result<Option<(Vec<RGBA8>, FontGlyph)>, PF2Error> {
+		let Some((def, bitmap)) = self.read_glyph(code_point)? else {
+			return Ok(None);
+		};
+		let rasterizer = self.rasterizer();
+		let (width, height) = rasterizer.scaled_char_size(&def);
+		let mut texture_data = vec![self.background; width * height];
+		rasterizer.rasterize_char_bitmap(&def, &bitmap, &mut texture_data, width);
+		// An empty glyph gets an empty buffer and a zero texture size.
+		let glyph = rasterizer.make_glyph(&def, (0, 0), (width.max(1), height.max(1)));
+		Ok(Some((texture_data, glyph)))
+	}
+
+	/// Decodes a glyph bitmap at its native size, ignoring supersampling, into its width,
+	/// height and one coverage byte (0 or 255) per pixel. Fails with
+	/// `PF2Error::HeadersNotParsed` unless `parse_headers()` was called first and returns
+	/// `None` if the font has no glyph for the code point.
+	pub fn glyph_bitmap(&self, code_point: u32) -> Result<Option<(u16, u16, Vec<u8>)>, PF2Error> {
+		let Some((def, bitmap)) = self.read_glyph(code_point)? else {
+			return Ok(None);
+		};
+		let rasterizer = GlyphRasterizer { supersampling: 1, outline: 0, sdf_spread: 0, ..self.rasterizer() };
+		let mut coverage = vec![0; def.width as usize * def.height as usize];
+		rasterizer.rasterize_char_bitmap(&def, &bitmap, &mut coverage, def.width as usize);
+		Ok(Some((def.width, def.height, coverage)))
+	}
+
+	fn read_glyph(&self, code_point: u32) -> Result<Option<(PF2CharDef, Vec<u8>)>, PF2Error> {
+		if !self.headers_parsed {
+			return Err(PF2Error::HeadersNotParsed);
+		}
+		let Some(&(offset, _)) = self.character_index.get(&code_point) else {
+			return Ok(None);
+		};
+		self.reader.borrow_mut().seek(offset as u64)?;
+		let def = self.read_char_def()?;
+		let mut bitmap = Vec::new();
+		self.read_char_bitmap(code_point, &def, &mut bitmap)?;
+		Ok(Some((def, bitmap)))
+	}
+
+	/// How long the steps of the last `load()`, `load_grayscale()`, `rasterize()` or
+	/// `load_metrics_only()` took.
+	pub fn timings(&self) -> LoadTimings {
+		self.timings
+	}
+
+	/// Atlas width and height in texels, valid once `load()` has completed.
+	pub fn texture_size(&self) -> (usize, usize) {
+		(self.texture_width, self.texture_height)
+	}
+
+	/// Parses the font if needed and rasterizes a new atlas. Calling it again on the same
+	/// loader gives the same result; use `reset` to load a different font. The atlas is
+	/// allocated once and moved into the result, the loader keeps no copy of it.
+	pub fn load(&mut self) -> Result<LoadedFont, PF2Error> {
+		self.load_pixels()
+	}
+
+	/// The second half of `load()`: reads the glyphs from DATA and builds the atlas out of
+	/// the character index found by `parse_headers()`, failing with
+	/// `PF2Error::HeadersNotParsed` if that wasn't called successfully first. In between the
+	/// two, the index can be inspected with `contains` and the loader reconfigured, e.g. with
+	/// `with_codepoint_filter`.
+	pub fn rasterize(&mut self) -> Result<LoadedFont, PF2Error> {
+		self.timings = LoadTimings::default();
+		self.rasterize_pixels()
+	}
+
+	/// Same as `rasterize()`, but the atlas holds a single coverage byte per texel.
+	pub fn rasterize_grayscale(&mut self) -> Result<LoadedFont<Vec<u8>>, PF2Error> {
+		self.timings = LoadTimings::default();
+		self.rasterize_pixels()
+	}
+
+	/// Lays the atlas out like `load()`, but never allocates it: glyphs are rasterized one
+	/// at a time into a reused buffer and passed to `upload` with their code point, the glyph
+	/// (its texture coordinates point into an atlas of `texture_size()`) and the width and
+	/// height of the pixels, e.g. to copy each one into a GPU texture right away.
+	pub fn load_streaming<F: FnMut(u32, &FontGlyph, &[RGBA8], usize, usize)>(&mut self, mut upload: F) -> Result<(), PF2Error> {
+		self.parse_headers()?;
+		let mut chars = Vec::new();
+		self.read_chars(&mut chars, None)?;
+		let positions = self.layout(&chars)?;
+		let rasterizer = self.rasterizer();
+		let mut bitmap = Vec::new();
+		let mut pixels = Vec::new();
+		for (entry, &position) in chars.iter().zip(&positions) {
+			self.reader.get_mut().seek(entry.bitmap_offset)?;
+			bitmap.clear();
+			self.read_char_bitmap(entry.code_point, &entry.def, &mut bitmap)?;
+			let (width, height) = rasterizer.scaled_char_size(&entry.def);
+			pixels.clear();
+			pixels.resize(width * height, self.background);
+			rasterizer.rasterize_char_bitmap(&entry.def, &bitmap, &mut pixels, width);
+			let glyph = rasterizer.make_atlas_glyph(position, &entry.def);
+			upload(entry.code_point, &glyph, &pixels, width, height);
+		}
+		Ok(())
+	}
+
+	/// Same as `load()`, but the atlas holds a single coverage byte per texel.
+	pub fn load_grayscale(&mut self) -> Result<LoadedFont<Vec<u8>>, PF2Error> {
+		self.load_pixels()
+	}
+
+	/// Reads the metrics and every glyph's placement relative to the pen for measuring text,
+	/// e.g. with `measure`, without reading glyph bitmaps or allocating an atlas. The glyphs'
+	/// texture coordinates and rectangles are all zero.
+	pub fn load_metrics_only(&mut self) -> Result<(FontMetrics, HashMap<u32, FontGlyph>), PF2Error> {
+		let mut stopwatch = Stopwatch::start();
+		self.parse_headers()?;
+		self.timings = LoadTimings { parse: stopwatch.lap(), ..LoadTimings::default() };
+		let mut chars = Vec::new();
+		self.read_chars(&mut chars, None)?;
+		let rasterizer = self.rasterizer();
+		let glyphs = chars.iter().map(|entry| (entry.code_point, FontGlyph {
+			tex_coord: Vec2::ZERO,
+			tex_size: Vec2::ZERO,
+			tex_rect: (0, 0, 0, 0),
+			..rasterizer.make_glyph(&entry.def, (0, 0), (1, 1))
+		})).collect();
+		self.timings.read = stopwatch.lap();
+		Ok((self.metrics(), glyphs))
+	}
+
+	/// Whether the alpha of RGBA atlases is plain glyph coverage, which is what `draw_text` needs.
+	fn coverage_alpha(&self) -> bool {
+		self.foreground.a == 255 && self.background.a == 0 && self.outline == 0 && self.sdf_spread == 0
+	}
+
+	fn load_pixels<P: AtlasPixel>(&mut self) -> Result<LoadedFont<Vec<P>>, PF2Error> {
+		let mut stopwatch = Stopwatch::start();
+		self.parse_headers()?;
+		self.timings = LoadTimings { parse: stopwatch.lap(), ..LoadTimings::default() };
+		self.rasterize_pixels()
+	}
+
+	fn rasterize_pixels<P: AtlasPixel>(&mut self) -> Result<LoadedFont<Vec<P>>, PF2Error> {
+		if !self.headers_parsed {
+			return Err(PF2Error::HeadersNotParsed);
+		}
+		let mut texture_data = Vec::<P>::new();
+		self.parse_data_section(&mut texture_data)?;
+		let mut glyphs = HashMap::<u32, FontGlyph>::new();
+		core::mem::swap(&mut self.glyphs, &mut glyphs);
+		let notdef_box = glyphs.remove(&NOTDEF_BOX);
+		let font = LoadedFont::new(texture_data, self.texture_width, self.texture_height, glyphs, self.metrics(), self.coverage_alpha());
+		Ok(LoadedFont { empty_box: notdef_box.unwrap_or(font.empty_box), ..font })
+	}
+
+	/// Loads every font into one shared atlas using the options of the first loader.
+	/// Grid cells are sized to fit the largest glyph of any font.
+	fn load_merged<P: AtlasPixel>(loaders: &mut [Self], collision: CodePointCollision) -> Result<LoadedFont<Vec<P>>, PF2Error> {
+		let mut chars = Vec::<PF2CharEntry>::new();
+		let mut bitmaps = Vec::new();
+		let mut merged = HashMap::<u32, usize>::new();
+		for loader in loaders.iter_mut() {
+			loader.parse_headers()?;
+			let mut font_chars = Vec::new();
+			loader.read_chars(&mut font_chars, Some(&mut bitmaps))?;
+			font_chars.sort_by_key(|entry| entry.index);
+			for entry in font_chars {
+				match merged.get(&entry.code_point) {
+					Some(_) if collision == CodePointCollision::Error => {
+						return Err(PF2Error::DuplicateCodePoint(entry.code_point));
+					}
+					Some(&i) => chars[i] = entry,
+					None => {
+						merged.insert(entry.code_point, chars.len());
+						chars.push(entry);
+					}
+				}
+			}
+		}
+		// Grid layout places glyphs by index, so renumber them in the merged order.
+		for (i, entry) in chars.iter_mut().enumerate() {
+			entry.index = i;
+		}
+		let max_width = loaders.iter().map(|loader| loader.max_width).max();
+		let max_height = loaders.iter().map(|loader| loader.max_height).max();
+		let [host, ..] = loaders else {
+			return Err(PF2Error::NoFonts);
+		};
+		let metrics = host.metrics();
+		host.max_width = max_width.unwrap_or_default();
+		host.max_height = max_height.unwrap_or_default();
+		host.push_notdef_box(&mut chars, &mut bitmaps);
+		let mut texture_data = Vec::<P>::new();
+		host.build_atlas(&chars, &bitmaps, &mut texture_data)?;
+		let mut glyphs = HashMap::<u32, FontGlyph>::new();
+		core::mem::swap(&mut host.glyphs, &mut glyphs);
+		let notdef_box = glyphs.remove(&NOTDEF_BOX);
+		let font = LoadedFont::new(texture_data, host.texture_width, host.texture_height, glyphs, metrics, host.coverage_alpha());
+		Ok(LoadedFont { empty_box: notdef_box.unwrap_or(font.empty_box), ..font })
+	}
+}
+
+/// Loads several fonts into a single atlas, e.g. a text font followed by fallback fonts
+/// for other scripts. Collisions are resolved according to `collision` and the metrics
+/// are taken from the first font; use `LoadedFont::with_metrics` to override them.
+/// Glyph sizes stay relative to the point size of the font each glyph came from.
+pub fn load_many(sources: &[&[u8]], collision: CodePointCollision) -> Result<LoadedFont, PF2Error> {
+	PF2LoaderBuilder::new().load_many_rgba(sources, collision)
+}
+
+/// Reads a whole font from `reader` without blocking the executor, then parses it and
+/// rasterizes the atlas on tokio's blocking thread pool. Must be called within a tokio runtime.
+#[cfg(feature = "tokio")]
+pub async fn load_async<R>(mut reader: R) -> Result<LoadedFont, PF2Error>
+where
+	R: tokio::io::AsyncRead + tokio::io::AsyncSeek + Unpin
+{
+	use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+	reader.seek(std::io::SeekFrom::Start(0)).await?;
+	let mut data = Vec::new();
+	reader.read_to_end(&mut data).await?;
+	tokio::task::spawn_blocking(move || PF2Loader::from_reader(std::io::Cursor::new(data)).load())
+		.await
+		.map_err(std::io::Error::from)?
+}
+
+/// Everything `load()` produces: the atlas texture, its dimensions in texels,
+/// the glyphs keyed by code point and the font metrics.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LoadedFont<T = Vec<RGBA8>> {
+	texture: T,
+	texture_width: usize,
+	texture_height: usize,
+	glyphs: HashMap<u32, FontGlyph>,
+	metrics: FontMetrics,
+	notdef: u32,
+	empty_box: FontGlyph,
+	kerning: HashMap<(u32, u32), f32>,
+	coverage_alpha: bool
+}
+
+impl<T> LoadedFont<T> {
+	fn new(texture: T, texture_width: usize, texture_height: usize, glyphs: HashMap<u32, FontGlyph>, metrics: FontMetrics, coverage_alpha: bool) -> Self {
+		LoadedFont {
+			texture,
+			texture_width,
+			texture_height,
+			glyphs,
+			metrics,
+			notdef: 0,
+			empty_box: FontGlyph::empty_box(&metrics),
+			kerning: HashMap::new(),
+			coverage_alpha
+		}
+	}
+
+	/// Borrows the atlas without copying it, e.g. to upload it to the GPU before dropping
+	/// the whole font. This is the only allocation holding the texels.
+	pub fn texture(&self) -> &T {
+		&self.texture
+	}
+
+	pub fn into_texture(self) -> T {
+		self.texture
+	}
+
+	/// Atlas width and height in texels.
+	pub fn size(&self) -> (usize, usize) {
+		(self.texture_width, self.texture_height)
+	}
+
+	pub fn glyph(&self, code_point: u32) -> Option<&FontGlyph> {
+		self.glyphs.get(&code_point)
+	}
+
+	/// Looks up a glyph, falling back to the notdef glyph (code point 0 unless changed with
+	/// `with_notdef`) for code points the font doesn't have. If the font has no notdef glyph
+	/// either, a hollow box half an em wide and `ascent` tall is returned, which the loader
+	/// bakes into the atlas for fonts without a glyph at code point 0.
+	pub fn glyph_or_notdef(&self, code_point: u32) -> &FontGlyph {
+		self.glyphs.get(&code_point)
+			.or_else(|| self.glyphs.get(&self.notdef))
+			.unwrap_or(&self.empty_box)
+	}
+
+	pub fn with_notdef(mut self, code_point: u32) -> Self {
+		self.notdef = code_point;
+		self
+	}
+
+	pub fn glyphs(&self) -> &HashMap<u32, FontGlyph> {
+		&self.glyphs
+	}
+
+	/// Same glyphs as `glyphs()`, but in ascending code point order.
+	pub fn glyphs_sorted(&self) -> impl Iterator<Item = (u32, &FontGlyph)> {
+		let mut glyphs: Vec<_> = self.glyphs.iter().map(|(&code_point, glyph)| (code_point, glyph)).collect();
+		glyphs.sort_unstable_by_key(|&(code_point, _)| code_point);
+		glyphs.into_iter()
+	}
+
+	/// The glyphs as a `Vec` indexed by a compact glyph id plus the code point to id mapping,
+	/// for renderers that keep glyphs in an array (e.g. a GPU buffer). Ids are assigned in
+	/// ascending code point order, which PFF2 requires for the character index, so without a
+	/// codepoint filter they match the file's glyph order.
+	pub fn indexed_glyphs(&self) -> (Vec<FontGlyph>, HashMap<u32, usize>) {
+		let (code_points, glyphs): (Vec<u32>, Vec<FontGlyph>) = self.glyphs_sorted()
+			.map(|(code_point, glyph)| (code_point, *glyph))
+			.unzip();
+		let ids = code_points.into_iter().enumerate().map(|(id, code_point)| (code_point, id)).collect();
+		(glyphs, ids)
+	}
+
+	pub fn metrics(&self) -> FontMetrics {
+		self.metrics
+	}
+
+	pub fn with_metrics(mut self, metrics: FontMetrics) -> Self {
+		self.metrics = metrics;
+		// A baked box keeps the size of the font it was rasterized for, like every other glyph.
+		if self.empty_box.tex_rect == (0, 0, 0, 0) {
+			self.empty_box = FontGlyph::empty_box(&metrics);
+		}
+		self
+	}
+
+	/// Like the free function `measure`, but missing characters advance by `glyph_or_notdef`
+	/// and kerning pairs are applied, so the width matches what `draw_text` covers.
+	pub fn measure(&self, text: &str) -> Vec2 {
+		let width: f32 = text.chars().map(|c| self.glyph_or_notdef(c as u32).width).sum();
+		Vec2::new(width + self.kerning_sum(text), self.metrics.ascent + self.metrics.descent)
+	}
+
+	/// Pen advance down to the next glyph in top-to-bottom text, in the same units as
+	/// `FontGlyph::width`. PFF2 has no vertical metrics, so it is synthesized as the line
+	/// height `ascent + descent`, or the glyph's own height if that is taller.
+	pub fn vertical_advance(&self, code_point: u32) -> f32 {
+		(self.metrics.ascent + self.metrics.descent).max(self.glyph_or_notdef(code_point).size.y)
+	}
+
+	/// Extra advance between `left` and `right` on top of the width of `left`, in the same
+	/// units as `FontGlyph::width`. PFF2 fonts carry no kerning, so pairs only come from
+	/// `set_kern` and `with_kerning`; pairs without an entry are 0.
+	pub fn kern(&self, left: u32, right: u32) -> f32 {
+		self.kerning.get(&(left, right)).copied().unwrap_or(0.0)
+	}
+
+	pub fn set_kern(&mut self, left: u32, right: u32, amount: f32) {
+		self.kerning.insert((left, right), amount);
+	}
+
+	/// Replaces the whole kerning table.
+	pub fn with_kerning(mut self, kerning: HashMap<(u32, u32), f32>) -> Self {
+		self.kerning = kerning;
+		self
+	}
+
+	pub fn kerning(&self) -> &HashMap<(u32, u32), f32> {
+		&self.kerning
+	}
+
+	fn kerning_sum(&self, text: &str) -> f32 {
+		if self.kerning.is_empty() {
+			return 0.0;
+		}
+		text.chars().zip(text.chars().skip(1)).map(|(left, right)| self.kern(left as u32, right as u32)).sum()
+	}
+
+	fn map_texture<U>(self, f: impl FnOnce(T) -> U) -> LoadedFont<U> {
+		LoadedFont {
+			texture: f(self.texture),
+			texture_width: self.texture_width,
+			texture_height: self.texture_height,
+			glyphs: self.glyphs,
+			metrics: self.metrics,
+			notdef: self.notdef,
+			empty_box: self.empty_box,
+			kerning: self.kerning,
+			coverage_alpha: self.coverage_alpha
+		}
+	}
+}
+
+/// Draws a single line of text into an RGBA buffer `dest_width` texels wide, starting with
+/// the pen at `pen` on the baseline (y grows downwards). Glyphs are drawn at the font's
+/// point size in pixels, their coverage tinted by `color` and blended over `dest`.
+/// Characters missing from the font advance like `LoadedFont::glyph_or_notdef`, kerning
+/// pairs are applied and anything outside `dest` is clipped. The atlas alpha is used as
+/// coverage, so fonts loaded with an opaque foreground over a transparent background
+/// (e.g. the default or `AtlasLayout::AlphaOnly`) and without outline or SDF are the only
+/// ones accepted, others fail with `PF2Error::NoCoverageAlpha`.
+pub fn draw_text(font: &LoadedFont, dest: &mut [RGBA8], dest_width: usize, text: &str, pen: Vec2, color: RGBA8) -> Result<(), PF2Error> {
+	if !font.coverage_alpha {
+		return Err(PF2Error::NoCoverageAlpha);
+	}
+	if dest_width == 0 {
+		return Ok(());
+	}
+	let dest_height = dest.len() / dest_width;
+	let (texture_width, _) = font.size();
+	let scale = font.metrics().point_size;
+	let mut pen = pen;
+	let mut previous = None;
+	for c in text.chars() {
+		if let Some(previous) = previous {
+			pen.x += font.kern(previous, c as u32) * scale;
+		}
+		previous = Some(c as u32);
+		let glyph = font.glyph_or_notdef(c as u32);
+		let x0 = (pen.x + glyph.baseline_offset().x * scale).round() as isize;
+		let y0 = (pen.y + glyph.baseline_offset().y * scale).round() as isize;
+		let width = (glyph.size.x * scale).round() as usize;
+		let height = (glyph.size.y * scale).round() as usize;
+		let (tex_x, tex_y, tex_width, tex_height) = glyph.tex_rect;
+		let (tex_x, tex_y, tex_width, tex_height) = (tex_x as usize, tex_y as usize, tex_width as usize, tex_height as usize);
+		pen.x += glyph.width * scale;
+		if tex_width == 0 || tex_height == 0 {
+			continue;
+		}
+		for y in 0..height {
+			let dest_y = y0 + y as isize;
+			if dest_y < 0 || dest_y as usize >= dest_height {
+				continue;
+			}
+			// Nearest-neighbour sampling, which is an exact copy unless the atlas is supersampled.
+			let row = (tex_y + y * tex_height / height) * texture_width;
+			for x in 0..width {
+				let dest_x = x0 + x as isize;
+				if dest_x < 0 || dest_x as usize >= dest_width {
+					continue;
+				}
+				let coverage = font.texture[row + tex_x + x * tex_width / width].a;
+				let pixel = &mut dest[dest_y as usize * dest_width + dest_x as usize];
+				*pixel = blend(*pixel, color, coverage);
+			}
+		}
+	}
+	Ok(())
+}
+
+/// Composites `color` with its alpha scaled by `coverage` over `dest`, both non-premultiplied.
+fn blend(dest: RGBA8, color: RGBA8, coverage: u8) -> RGBA8 {
+	let src_alpha = color.a as u32 * coverage as u32 / 255;
+	let dest_alpha = dest.a as u32 * (255 - src_alpha) / 255;
+	let alpha = src_alpha + dest_alpha;
+	if alpha == 0 {
+		return dest;
+	}
+	let mix = |src: u8, dst: u8| ((src as u32 * src_alpha + dst as u32 * dest_alpha) / alpha) as u8;
+	RGBA8::new(mix(color.r, dest.r), mix(color.g, dest.g), mix(color.b, dest.b), alpha as u8)
+}
+
+/// Writes the atlas of `font` to an 8-bit RGBA PNG file, mostly useful to inspect the packing.
+#[cfg(feature = "png")]
+pub fn save_atlas_png(font: &LoadedFont, path: &std::path::Path) -> std::io::Result<()> {
+	use rgb::ComponentBytes;
+	let (width, height) = font.size();
+	let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+	let mut encoder = png::Encoder::new(file, width as u32, height as u32);
+	encoder.set_color(png::ColorType::Rgba);
+	encoder.set_depth(png::BitDepth::Eight);
+	let mut writer = encoder.write_header()?;
+	writer.write_image_data(font.texture().as_bytes())?;
+	writer.finish()?;
+	Ok(())
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AtlasTexture {
+	Rgba(Vec<RGBA8>),
+	Grayscale(Vec<u8>)
+}
+
+#[derive(Debug, Clone)]
+pub struct PF2LoaderBuilder {
+	supersampling: u32,
+	padding: usize,
+	packing: AtlasPacking,
+	max_texture_dimension: Option<usize>,
+	pow2_dimensions: bool,
+	trim_rows: bool,
+	codepoint_filter: Option<fn(u32) -> bool>,
+	outline: usize,
+	sdf_spread: usize,
+	colors: (RGBA8, RGBA8),
+	strict: bool,
+	byte_order: Option<ByteOrder>,
+	grayscale: bool
+}
+
+impl Default for PF2LoaderBuilder {
+	fn default() -> Self {
+		PF2LoaderBuilder {
+			supersampling: 1,
+			padding: 0,
+			packing: AtlasPacking::Grid,
+			max_texture_dimension: None,
+			pow2_dimensions: false,
+			trim_rows: false,
+			codepoint_filter: None,
+			outline: 0,
+			sdf_spread: 0,
+			colors: AtlasLayout::default().colors(),
+			strict: false,
+			byte_order: None,
+			grayscale: false
+		}
+	}
+}
+
+impl PF2LoaderBuilder {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// See `PF2Loader::with_supersampling`.
+	pub fn supersample(mut self, factor: u32) -> Self {
+		self.supersampling = factor;
+		self
+	}
+
+	/// See `PF2Loader::with_padding`.
+	pub fn padding(mut self, padding: usize) -> Self {
+		self.padding = padding;
+		self
+	}
+
+	pub fn packing(mut self, packing: AtlasPacking) -> Self {
+		self.packing = packing;
+		self
+	}
+
+	/// See `PF2Loader::with_outline`.
+	pub fn outline(mut self, radius: usize) -> Self {
+		self.outline = radius;
+		self
+	}
+
+	/// See `PF2Loader::with_sdf`.
+	pub fn sdf(mut self, spread: usize) -> Self {
+		self.sdf_spread = spread;
+		self
+	}
+
+	/// See `PF2Loader::with_strict`.
+	pub fn strict(mut self, strict: bool) -> Self {
+		self.strict = strict;
+		self
+	}
+
+	/// See `PF2Loader::with_byte_order`.
+	pub fn byte_order(mut self, byte_order: ByteOrder) -> Self {
+		self.byte_order = Some(byte_order);
+		self
+	}
+
+	/// See `PF2Loader::with_colors`.
+	pub fn colors(mut self, foreground: RGBA8, background: RGBA8) -> Self {
+		self.colors = (foreground, background);
+		self
+	}
+
+	/// See `PF2Loader::with_layout`.
+	pub fn layout(mut self, layout: AtlasLayout) -> Self {
+		self.colors = layout.colors();
+		self
+	}
+
+	/// See `PF2Loader::with_max_texture_dimension`.
+	pub fn max_texture_dimension(mut self, max_dimension: usize) -> Self {
+		self.max_texture_dimension = Some(max_dimension);
+		self
+	}
+
+	/// See `PF2Loader::with_pow2_dimensions`.
+	pub fn pow2_dimensions(mut self, pow2_dimensions: bool) -> Self {
+		self.pow2_dimensions = pow2_dimensions;
+		self
+	}
+
+	/// See `PF2Loader::with_trim_rows`.
+	pub fn trim_rows(mut self, trim_rows: bool) -> Self {
+		self.trim_rows = trim_rows;
+		self
+	}
+
+	/// See `PF2Loader::with_codepoint_filter`.
+	pub fn codepoint_filter(mut self, filter: fn(u32) -> bool) -> Self {
+		self.codepoint_filter = Some(filter);
+		self
+	}
+
+	/// Makes `load()` produce an `AtlasTexture::Grayscale` atlas instead of an RGBA one.
+	pub fn grayscale(mut self, grayscale: bool) -> Self {
+		self.grayscale = grayscale;
+		self
+	}
+
+	pub fn build<'a>(&self, data: &'a [u8]) -> PF2Loader<SliceReader<'a>> {
+		self.build_from_reader(SliceReader::new(data))
+	}
+
+	pub fn build_from_reader<R: PF2Source>(&self, reader: R) -> PF2Loader<R> {
+		let loader = PF2Loader::from_reader(reader)
+			.with_supersampling(self.supersampling)
+			.with_padding(self.padding)
+			.with_packing(self.packing)
+			.with_outline(self.outline)
+			.with_sdf(self.sdf_spread)
+			.with_pow2_dimensions(self.pow2_dimensions)
+			.with_trim_rows(self.trim_rows)
+			.with_colors(self.colors.0, self.colors.1)
+			.with_strict(self.strict);
+		let loader = match self.byte_order {
+			Some(byte_order) => loader.with_byte_order(byte_order),
+			None => loader
+		};
+		let loader = match self.codepoint_filter {
+			Some(filter) => loader.with_codepoint_filter(filter),
+			None => loader
+		};
+		match self.max_texture_dimension {
+			Some(max_dimension) => loader.with_max_texture_dimension(max_dimension),
+			None => loader
+		}
+	}
+
+	pub fn load(&self, data: &[u8]) -> Result<LoadedFont<AtlasTexture>, PF2Error> {
+		let mut loader = self.build(data);
+		if self.grayscale {
+			Ok(loader.load_grayscale()?.map_texture(AtlasTexture::Grayscale))
+		} else {
+			Ok(loader.load()?.map_texture(AtlasTexture::Rgba))
+		}
+	}
+
+	/// See the free function `load_many`.
+	pub fn load_many(&self, sources: &[&[u8]], collision: CodePointCollision) -> Result<LoadedFont<AtlasTexture>, PF2Error> {
+		if self.grayscale {
+			Ok(PF2Loader::load_merged(&mut self.build_many(sources), collision)?.map_texture(AtlasTexture::Grayscale))
+		} else {
+			Ok(self.load_many_rgba(sources, collision)?.map_texture(AtlasTexture::Rgba))
+		}
+	}
+
+	fn load_many_rgba(&self, sources: &[&[u8]], collision: CodePointCollision) -> Result<LoadedFont, PF2Error> {
+		PF2Loader::load_merged(&mut self.build_many(sources), collision)
+	}
+
+	fn build_many<'a>(&self, sources: &[&'a [u8]]) -> Vec<PF2Loader<SliceReader<'a>>> {
+		sources.iter().map(|data| self.build(data)).collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::font_writer::{PF2GlyphImage, PF2Writer};
+
+	const DROID_SANS: &[u8] = include_bytes!("../assets/DroidSans-32.pf2");
+
+	fn image(width: u16, height: u16, pixels: &[bool]) -> PF2GlyphImage<'_> {
+		PF2GlyphImage { width, height, x_offset: 0, y_offset: 0, device_width: width as i16 + 1, pixels }
+	}
+
+	#[test]
+	fn grid_of_empty_glyphs() {
+		let data = PF2Writer::new().max_size(8, 8).glyph(0x20, image(0, 0, &[])).write();
+		let font = PF2Loader::new(&data).load().unwrap();
+		assert_eq!(font.glyph(0x20).unwrap().tex_rect(), (0, 0, 0, 0));
+		assert!(PF2Loader::new(&data).with_max_texture_dimension(16).load().is_ok());
+		let data = PF2Writer::new().max_size(8, 8).glyph(0x20, image(0, 4, &[])).write();
+		assert!(PF2Loader::new(&data).with_max_texture_dimension(16).load().is_ok());
+
+		let font = PF2Loader::new(DROID_SANS).with_codepoint_filter(|c| c == 0x20).load().unwrap();
+		assert_eq!(font.glyphs().len(), 1);
+	}
+
+	#[test]
+	fn trimmed_rows_of_empty_glyphs() {
+		let ink = [true; 16];
+		// An empty notdef glyph keeps the loader from adding a tofu box taller than 'A'.
+		let mut writer = PF2Writer::new().glyph(0, image(0, 0, &[])).glyph(0x41, image(4, 4, &ink));
+		for code_point in 0x2000..0x2008 {
+			writer = writer.glyph(code_point, image(0, 0, &[]));
+		}
+		let data = writer.write();
+		for packing in [AtlasPacking::Grid, AtlasPacking::Shelf] {
+			let font = PF2Loader::new(&data).with_packing(packing).with_trim_rows(true).load().unwrap();
+			assert_eq!(font.size().1, 4);
+			assert!(font.glyphs().values().all(|glyph| glyph.tex_coord().y <= 1.0));
+		}
+
+		let font = PF2Loader::new(DROID_SANS)
+			.with_trim_rows(true)
+			.with_codepoint_filter(|c| c < 0x80 || (0x2000..0x200C).contains(&c))
+			.load()
+			.unwrap();
+		assert!(font.glyph(0x2000).is_some());
+	}
+
+	#[test]
+	fn update_glyph_after_reset() {
+		let pixels = [true; 16];
+		let data = PF2Writer::new().glyph('A' as u32, image(4, 4, &pixels)).write();
+		let def = PF2CharDef { width: 4, height: 4, x_offset: 0, y_offset: 0, device_width: 5 };
+		let bitmap = [0xFF; 2];
+		for packing in [AtlasPacking::Grid, AtlasPacking::Shelf] {
+			let mut loader = PF2Loader::new(&data[..]).with_packing(packing).with_padding(1);
+			let mut font = loader.load().unwrap();
+			assert!(loader.update_glyph(&mut font, 'A' as u32, def, &bitmap).unwrap());
+			loader.reset(SliceReader::new(&data));
+			assert!(matches!(loader.update_glyph(&mut font, 'A' as u32, def, &bitmap), Err(PF2Error::FontMismatch)));
+		}
+	}
+
+	#[test]
+	fn glyph_before_parse_headers() {
+		let mut loader = PF2Loader::new(DROID_SANS);
+		assert!(matches!(loader.load_glyph('A' as u32), Err(PF2Error::HeadersNotParsed)));
+		assert!(matches!(loader.glyph_bitmap('A' as u32), Err(PF2Error::HeadersNotParsed)));
+		loader.parse_headers().unwrap();
+		assert!(loader.load_glyph('A' as u32).unwrap().is_some());
+		assert!(loader.glyph_bitmap(0x10FFFF).unwrap().is_none());
+	}
+
+	#[test]
+	fn draw_text_needs_coverage_alpha() {
+		let mut dest = vec![RGBA8::new(0, 0, 0, 255); 64 * 64];
+		let color = RGBA8::new(255, 0, 0, 255);
+		for loader in [PF2Loader::new(DROID_SANS), PF2Loader::new(DROID_SANS).with_layout(AtlasLayout::AlphaOnly)] {
+			let font = loader.with_codepoint_filter(|c| c == 'A' as u32).load().unwrap();
+			draw_text(&font, &mut dest, 64, "A", Vec2::new(8.0, 40.0), color).unwrap();
+		}
+		assert!(dest.contains(&color));
+		let opaque = PF2Loader::new(DROID_SANS).with_colors(RGBA8::new(255, 255, 255, 255), RGBA8::new(0, 0, 0, 255));
+		for loader in [opaque, PF2Loader::new(DROID_SANS).with_outline(1), PF2Loader::new(DROID_SANS).with_sdf(4)] {
+			let font = loader.with_codepoint_filter(|c| c == 'A' as u32).load().unwrap();
+			assert!(matches!(draw_text(&font, &mut dest, 64, "A", Vec2::ZERO, color), Err(PF2Error::NoCoverageAlpha)));
+		}
+	}
+
+	#[test]
+	fn notdef_box_in_atlas() {
+		let font = PF2Loader::new(DROID_SANS).load().unwrap();
+		assert!(font.glyph(0).is_none() && font.glyph(NOTDEF_BOX).is_none());
+		let tofu = *font.glyph_or_notdef(0x10FFFF);
+		let (x, y, width, height) = tofu.tex_rect;
+		assert!(width > 0 && height > 0);
+		let stride = font.size().0;
+		let texel = |tx: u32, ty: u32| font.texture()[(y + ty) as usize * stride + (x + tx) as usize].a;
+		assert_eq!((texel(0, 0), texel(width - 1, height - 1)), (255, 255));
+		assert_eq!(texel(width / 2, height / 2), 0);
+		let metrics = FontMetrics { point_size: 64.0, ..font.metrics() };
+		assert_eq!(font.with_metrics(metrics).glyph_or_notdef(0x10FFFF), &tofu);
+
+		let pixels = [true; 16];
+		let data = PF2Writer::new().glyph(0, image(4, 4, &pixels)).glyph('A' as u32, image(4, 4, &pixels)).write();
+		let font = PF2Loader::new(&data).load().unwrap();
+		assert_eq!(font.glyph_or_notdef(0x10FFFF), font.glyph(0).unwrap());
+		assert_eq!(font.glyphs().len(), 2);
+	}
+
+	fn checkerboard_font(byte_order: ByteOrder) -> Vec<u8> {
+		let pixels: Vec<bool> = (0..35).map(|i| (i % 5 + i / 5) % 2 == 0).collect();
+		PF2Writer::new()
+			.byte_order(byte_order)
+			.glyph('A' as u32, PF2GlyphImage { width: 5, height: 7, x_offset: -1, y_offset: -2, device_width: 6, pixels: &pixels })
+			.glyph('B' as u32, image(3, 2, &pixels[..6]))
+			.write()
+	}
+
+	#[test]
+	fn little_endian_round_trip() {
+		let big = checkerboard_font(ByteOrder::BigEndian);
+		let little = checkerboard_font(ByteOrder::LittleEndian);
+		assert_ne!(big, little);
+		let mut loader = PF2Loader::new(&little);
+		let font = loader.load().unwrap();
+		assert_eq!(loader.byte_order(), ByteOrder::LittleEndian);
+		let expected = PF2Loader::new(&big).load().unwrap();
+		assert_eq!(font.texture(), expected.texture());
+		assert!(font.glyphs_sorted().eq(expected.glyphs_sorted()));
+		assert_eq!(font.glyph('A' as u32).unwrap().advance_px, 6.0);
+		assert!(PF2Loader::new(&little).with_byte_order(ByteOrder::LittleEndian).load().is_ok());
+		assert!(matches!(
+			PF2Loader::new(&little).with_byte_order(ByteOrder::BigEndian).load(),
+			Err(PF2Error::WrongByteOrder { expected: ByteOrder::BigEndian, found: ByteOrder::LittleEndian })
+		));
+	}
+
+	#[test]
+	fn duplicate_code_point() {
+		let pixels = [true; 4];
+		let data = PF2Writer::new().glyph('A' as u32, image(2, 2, &pixels)).glyph('A' as u32, image(2, 2, &pixels)).write();
+		assert!(matches!(PF2Loader::new(&data).load(), Err(PF2Error::DuplicateCodePoint(0x41))));
+		let single = PF2Writer::new().glyph('A' as u32, image(2, 2, &pixels)).write();
+		assert!(matches!(
+			load_many(&[&single, &single], CodePointCollision::Error),
+			Err(PF2Error::DuplicateCodePoint(0x41))
+		));
+	}
+
+	#[test]
+	fn strict_overlapping_glyph_data() {
+		// 'A' claims an 8x8 bitmap but only carries one byte of it, so it runs into 'B'.
+		let mut short = Vec::new();
+		for value in [8u16, 8, 0, 0, 9] {
+			short.extend_from_slice(&value.to_be_bytes());
+		}
+		short.push(0xFF);
+		let data = PF2Writer::new()
+			.raw_glyph('A' as u32, 0, short)
+			.glyph('B' as u32, image(8, 8, &[true; 64]))
+			.write();
+		assert!(PF2Loader::new(&data).load().is_ok());
+		assert!(matches!(PF2Loader::new(&data).with_strict(true).load(), Err(PF2Error::OverlappingGlyphData(_))));
+	}
+
+	#[test]
+	fn strict_unknown_section() {
+		let data = PF2Writer::new().max_size(8, 8).section(*b"XTRA", vec![1, 2, 3]).glyph('A' as u32, image(0, 0, &[])).write();
+		assert!(PF2Loader::new(&data).load().is_ok());
+		assert!(matches!(PF2Loader::new(&data).with_strict(true).load(), Err(PF2Error::UnknownSection(tag)) if &tag == b"XTRA"));
+	}
+
+	#[test]
+	fn truncated_section() {
+		let data = PF2Writer::new().name("Truncated Regular 16").glyph('A' as u32, image(2, 2, &[true; 4])).write();
+		// FILE takes 12 bytes, then NAME has an 8 byte header; cut its contents short.
+		assert_eq!(&data[12..16], b"NAME");
+		assert!(matches!(PF2Loader::new(&data[..24]).load(), Err(PF2Error::TruncatedSection(tag)) if &tag == b"NAME"));
+		assert!(PF2Loader::new(&data).load().is_ok());
 	}
 }
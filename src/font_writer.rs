@@ -0,0 +1,223 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use crate::font_loader::ByteOrder;
+
+/// A glyph bitmap for `PF2Writer::glyph`. `pixels` holds `width * height` flags,
+/// row by row from the top, `true` for ink.
+pub struct PF2GlyphImage<'a> {
+	pub width: u16,
+	pub height: u16,
+	pub x_offset: i16,
+	pub y_offset: i16,
+	pub device_width: i16,
+	pub pixels: &'a [bool]
+}
+
+struct PF2WriterGlyph {
+	code_point: u32,
+	flags: u8,
+	data: Vec<u8>
+}
+
+/// Assembles a PFF2 font in memory, e.g. to produce test inputs for `PF2Loader`
+/// without shipping binary fixtures. Only the sections the loader parses are covered.
+///
+/// Nothing is validated: glyphs are indexed in the order they were added, so adding
+/// a code point twice produces a duplicate CHIX entry just like a broken file would.
+/// Truncated inputs can be made by cutting the result of `write()`.
+pub struct PF2Writer {
+	byte_order: ByteOrder,
+	name: Option<String>,
+	family: Option<String>,
+	weight: Option<String>,
+	slant: Option<String>,
+	point_size: u16,
+	max_size: Option<(u16, u16)>,
+	ascent: u16,
+	descent: u16,
+	sections: Vec<([u8; 4], Vec<u8>)>,
+	glyphs: Vec<PF2WriterGlyph>
+}
+
+impl Default for PF2Writer {
+	fn default() -> Self {
+		PF2Writer {
+			byte_order: ByteOrder::BigEndian,
+			name: None,
+			family: None,
+			weight: None,
+			slant: None,
+			point_size: 16,
+			max_size: None,
+			ascent: 12,
+			descent: 4,
+			sections: Vec::new(),
+			glyphs: Vec::new()
+		}
+	}
+}
+
+impl PF2Writer {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Byte order of every number in the file. Real PFF2 files are big-endian.
+	pub fn byte_order(mut self, byte_order: ByteOrder) -> Self {
+		self.byte_order = byte_order;
+		self
+	}
+
+	pub fn name(mut self, name: &str) -> Self {
+		self.name = Some(name.into());
+		self
+	}
+
+	pub fn family(mut self, family: &str) -> Self {
+		self.family = Some(family.into());
+		self
+	}
+
+	pub fn weight(mut self, weight: &str) -> Self {
+		self.weight = Some(weight.into());
+		self
+	}
+
+	pub fn slant(mut self, slant: &str) -> Self {
+		self.slant = Some(slant.into());
+		self
+	}
+
+	pub fn point_size(mut self, point_size: u16) -> Self {
+		self.point_size = point_size;
+		self
+	}
+
+	/// Overrides MAXW and MAXH, which are otherwise the largest glyph width and height.
+	pub fn max_size(mut self, max_width: u16, max_height: u16) -> Self {
+		self.max_size = Some((max_width, max_height));
+		self
+	}
+
+	pub fn ascent(mut self, ascent: u16) -> Self {
+		self.ascent = ascent;
+		self
+	}
+
+	pub fn descent(mut self, descent: u16) -> Self {
+		self.descent = descent;
+		self
+	}
+
+	/// Adds an arbitrary section after the metadata, before CHIX.
+	pub fn section(mut self, tag: [u8; 4], data: Vec<u8>) -> Self {
+		self.sections.push((tag, data));
+		self
+	}
+
+	/// Adds an uncompressed glyph, packing `image.pixels` into a 1 bit per pixel bitmap.
+	pub fn glyph(self, code_point: u32, image: PF2GlyphImage) -> Self {
+		debug_assert!(image.pixels.len() == image.width as usize * image.height as usize);
+		let mut data = Vec::new();
+		for value in [image.width, image.height] {
+			self.push_u16(&mut data, value);
+		}
+		for value in [image.x_offset, image.y_offset, image.device_width] {
+			self.push_u16(&mut data, value as u16);
+		}
+		let bitmap_start = data.len();
+		data.resize(bitmap_start + image.pixels.len().div_ceil(8), 0);
+		for (i, _) in image.pixels.iter().enumerate().filter(|(_, &ink)| ink) {
+			data[bitmap_start + i / 8] |= 1 << (7 - i % 8);
+		}
+		self.raw_glyph(code_point, 0, data)
+	}
+
+	/// Adds a glyph whose character definition block (header and bitmap) is written verbatim,
+	/// with the given CHIX storage flags.
+	pub fn raw_glyph(mut self, code_point: u32, flags: u8, data: Vec<u8>) -> Self {
+		self.glyphs.push(PF2WriterGlyph { code_point, flags, data });
+		self
+	}
+
+	fn push_u16(&self, out: &mut Vec<u8>, value: u16) {
+		out.extend_from_slice(&match self.byte_order {
+			ByteOrder::BigEndian => value.to_be_bytes(),
+			ByteOrder::LittleEndian => value.to_le_bytes()
+		});
+	}
+
+	fn push_u32(&self, out: &mut Vec<u8>, value: u32) {
+		out.extend_from_slice(&match self.byte_order {
+			ByteOrder::BigEndian => value.to_be_bytes(),
+			ByteOrder::LittleEndian => value.to_le_bytes()
+		});
+	}
+
+	fn push_section(&self, out: &mut Vec<u8>, tag: &[u8; 4], data: &[u8]) {
+		out.extend_from_slice(tag);
+		self.push_u32(out, data.len() as u32);
+		out.extend_from_slice(data);
+	}
+
+	fn push_string_section(&self, out: &mut Vec<u8>, tag: &[u8; 4], value: &Option<String>) {
+		if let Some(value) = value {
+			let mut data = Vec::from(value.as_bytes());
+			data.push(0);
+			self.push_section(out, tag, &data);
+		}
+	}
+
+	fn push_u16_section(&self, out: &mut Vec<u8>, tag: &[u8; 4], value: u16) {
+		let mut data = Vec::new();
+		self.push_u16(&mut data, value);
+		self.push_section(out, tag, &data);
+	}
+
+	/// Serializes the font. Glyph bitmaps are placed in DATA in the order they were added.
+	pub fn write(&self) -> Vec<u8> {
+		let mut out = Vec::new();
+		self.push_section(&mut out, b"FILE", b"PFF2");
+		self.push_string_section(&mut out, b"NAME", &self.name);
+		self.push_string_section(&mut out, b"FAMI", &self.family);
+		self.push_string_section(&mut out, b"WEIG", &self.weight);
+		self.push_string_section(&mut out, b"SLAN", &self.slant);
+		let (max_width, max_height) = self.max_size.unwrap_or_else(|| {
+			// Width and height are the first two fields of every character definition.
+			let field = |glyph: &PF2WriterGlyph, i: usize| glyph.data.get(i..i + 2).map_or(0, |bytes| match self.byte_order {
+				ByteOrder::BigEndian => u16::from_be_bytes([bytes[0], bytes[1]]),
+				ByteOrder::LittleEndian => u16::from_le_bytes([bytes[0], bytes[1]])
+			});
+			let max_field = |i: usize| self.glyphs.iter().map(|glyph| field(glyph, i)).max().unwrap_or(0);
+			(max_field(0), max_field(2))
+		});
+		self.push_u16_section(&mut out, b"PTSZ", self.point_size);
+		self.push_u16_section(&mut out, b"MAXW", max_width);
+		self.push_u16_section(&mut out, b"MAXH", max_height);
+		self.push_u16_section(&mut out, b"ASCE", self.ascent);
+		self.push_u16_section(&mut out, b"DESC", self.descent);
+		for (tag, data) in &self.sections {
+			self.push_section(&mut out, tag, data);
+		}
+
+		const RECORD_LEN: usize = 9;
+		const SECTION_HEADER_LEN: usize = 8;
+		let mut offset = out.len() + SECTION_HEADER_LEN + self.glyphs.len() * RECORD_LEN + SECTION_HEADER_LEN;
+		let mut index = Vec::with_capacity(self.glyphs.len() * RECORD_LEN);
+		for glyph in &self.glyphs {
+			self.push_u32(&mut index, glyph.code_point);
+			index.push(glyph.flags);
+			self.push_u32(&mut index, offset as u32);
+			offset += glyph.data.len();
+		}
+		self.push_section(&mut out, b"CHIX", &index);
+
+		// DATA runs to the end of the file and is marked by an all-ones length.
+		out.extend_from_slice(b"DATA");
+		out.extend_from_slice(&u32::MAX.to_be_bytes());
+		for glyph in &self.glyphs {
+			out.extend_from_slice(&glyph.data);
+		}
+		out
+	}
+}
@@ -1,6 +1,35 @@
-use ahash::AHashMap as HashMap;
-use std::cmp::{min, max};
-use glam::Vec3;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::{min, max};
+use core::f32::consts::PI;
+use core::fmt::{self, Display, Formatter};
+use crate::{HashMap, HashSet};
+#[cfg(not(any(feature = "std", test)))]
+#[allow(unused_imports)] // See `math`.
+use crate::math::FloatExt;
+use glam::{Vec2, Vec3, Vec4};
+
+pub type Triangle = (u32, u32, u32);
+
+/// Subdivision limit used by `try_generate_mesh`: 655362 vertexes and 1310720 triangles.
+pub const DEFAULT_MAX_SUBDIVISION_COUNT: u32 = 8;
+
+#[derive(Debug)]
+pub enum MeshError {
+	TooManySubdivisions { requested: u32, max: u32 }
+}
+
+impl Display for MeshError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			MeshError::TooManySubdivisions { requested, max } => write!(
+				f, "Requested {} subdivisions, but at most {} are allowed", requested, max
+			)
+		}
+	}
+}
+
+impl core::error::Error for MeshError {}
 
 fn mid_vertex_for_edge(cache: &mut HashMap<(u32, u32), u32>, vertexes: &mut Vec<Vec3>, first: u32, second: u32) -> u32 {
 	let key = (min(first, second), max(first, second));
@@ -10,7 +39,7 @@ fn mid_vertex_for_edge(cache: &mut HashMap<(u32, u32), u32>, vertexes: &mut Vec<
 	})
 }
 
-fn subdivide_mesh(vertexes: &mut Vec<Vec3>, triangles: &Vec<(u32, u32, u32)>, cache: &mut HashMap<(u32, u32), u32>, result: &mut Vec<(u32, u32, u32)>) {
+fn subdivide_mesh(vertexes: &mut Vec<Vec3>, triangles: &Vec<Triangle>, cache: &mut HashMap<(u32, u32), u32>, result: &mut Vec<Triangle>) {
 	cache.clear();
 	result.clear();
 	for triangle in triangles {
@@ -28,12 +57,72 @@ fn subdivide_mesh(vertexes: &mut Vec<Vec3>, triangles: &Vec<(u32, u32, u32)>, ca
 	debug_assert!(cache.len() == triangles.len() + triangles.len() / 2);
 }
 
-pub fn generate_mesh(subdivision_count: u32) -> (Vec<Vec3>, Vec<(u32, u32, u32)>) {
-	const X: f32 = 0.525731112119133606;
-	const Z: f32 = 0.850650808352039932;
+/// Number of vertexes `generate_mesh` produces, `10 * 4^n + 2`. Saturates at `usize::MAX`.
+pub fn mesh_vertex_count(subdivision_count: u32) -> usize {
+	4usize.saturating_pow(subdivision_count).saturating_mul(10).saturating_add(2)
+}
+
+/// Number of triangles `generate_mesh` produces, `20 * 4^n`. Saturates at `usize::MAX`.
+pub fn mesh_triangle_count(subdivision_count: u32) -> usize {
+	4usize.saturating_pow(subdivision_count).saturating_mul(20)
+}
+
+/// Same as `generate_mesh`, but refuses subdivision counts above
+/// `DEFAULT_MAX_SUBDIVISION_COUNT` instead of trying to allocate a huge mesh.
+pub fn try_generate_mesh(subdivision_count: u32) -> Result<(Vec<Vec3>, Vec<Triangle>), MeshError> {
+	try_generate_mesh_with_limit(subdivision_count, DEFAULT_MAX_SUBDIVISION_COUNT)
+}
+
+pub fn try_generate_mesh_with_limit(subdivision_count: u32, max_subdivision_count: u32) -> Result<(Vec<Vec3>, Vec<Triangle>), MeshError> {
+	if subdivision_count > max_subdivision_count {
+		return Err(MeshError::TooManySubdivisions { requested: subdivision_count, max: max_subdivision_count });
+	}
+	Ok(generate_mesh(subdivision_count))
+}
+
+/// Generates a unit icosphere as `glam::Vec3` positions and index triples. For other math
+/// libraries, `generate_mesh_arrays` returns the same mesh as plain arrays.
+pub fn generate_mesh(subdivision_count: u32) -> (Vec<Vec3>, Vec<Triangle>) {
+	generate_mesh_radius(subdivision_count, 1.0)
+}
+
+/// A generated mesh together with the subdivision count it was generated with.
+#[derive(Debug, Clone)]
+pub struct MeshResult {
+	pub level: u32,
+	pub vertexes: Vec<Vec3>,
+	pub triangles: Vec<Triangle>
+}
+
+/// Same as `generate_mesh`, but keeps the subdivision count next to the buffers.
+pub fn generate_mesh_result(subdivision_count: u32) -> MeshResult {
+	let (vertexes, triangles) = generate_mesh(subdivision_count);
+	MeshResult { level: subdivision_count, vertexes, triangles }
+}
+
+/// Generates a sphere of the given radius centered at the origin. The radius is applied
+/// as is: zero collapses every vertex into the origin and a negative radius mirrors the
+/// sphere through the origin, which flips the triangle winding relative to the surface.
+pub fn generate_mesh_radius(subdivision_count: u32, radius: f32) -> (Vec<Vec3>, Vec<Triangle>) {
+	let mut vertexes = Vec::new();
+	let mut triangles = Vec::new();
+	generate_mesh_radius_into(subdivision_count, radius, &mut vertexes, &mut triangles);
+	(vertexes, triangles)
+}
+
+/// Same as `generate_mesh`, but clears and refills the given buffers, so their
+/// allocations are reused when meshes are generated repeatedly.
+pub fn generate_mesh_into(subdivision_count: u32, vertexes: &mut Vec<Vec3>, triangles: &mut Vec<Triangle>) {
+	generate_mesh_radius_into(subdivision_count, 1.0, vertexes, triangles)
+}
+
+fn icosahedron(vertexes: &mut Vec<Vec3>, triangles: &mut Vec<Triangle>) {
+	const X: f32 = 0.5257311;
+	const Z: f32 = 0.8506508;
 	const N: f32 = 0.0;
 	
-	let mut vertexes = vec![
+	vertexes.clear();
+	vertexes.extend_from_slice(&[
 		Vec3::new(-X, N, Z), 
 		Vec3::new(X, N, Z), 
 		Vec3::new(-X, N, -Z), 
@@ -46,14 +135,19 @@ pub fn generate_mesh(subdivision_count: u32) -> (Vec<Vec3>, Vec<(u32, u32, u32)>
 		Vec3::new(-Z, X, N), 
 		Vec3::new(Z, -X, N),
 		Vec3::new(-Z, -X, N)
-	];
+	]);
 
-	let mut triangles = vec![
+	triangles.clear();
+	triangles.extend_from_slice(&[
 		(0, 4, 1), (0, 9, 4), (9, 5, 4), (4, 5, 8), (4, 8, 1),
 		(8, 10, 1), (8, 3, 10), (5, 3, 8), (5, 2, 3), (2, 7, 3),
 		(7, 10, 3), (7, 6, 10), (7, 11, 6), (11, 0, 6), (0, 1, 6),
 		(6, 1, 10), (9, 0, 11), (9, 11, 2), (9, 2, 5), (7, 2, 11)
-	];
+	]);
+}
+
+fn generate_mesh_radius_into(subdivision_count: u32, radius: f32, vertexes: &mut Vec<Vec3>, triangles: &mut Vec<Triangle>) {
+	icosahedron(vertexes, triangles);
 
 	let mut predicted_vertex_count = vertexes.len();
 	let mut predicted_triangle_count = triangles.len();
@@ -70,14 +164,357 @@ pub fn generate_mesh(subdivision_count: u32) -> (Vec<Vec3>, Vec<(u32, u32, u32)>
 	let mut cache = HashMap::with_capacity(predicted_cache_size);
 
 	for _ in 0..subdivision_count {
-		subdivide_mesh(&mut vertexes, &triangles, &mut cache, &mut tmp_triangles);
-		std::mem::swap(&mut triangles, &mut tmp_triangles);
+		subdivide_mesh(vertexes, triangles, &mut cache, &mut tmp_triangles);
+		core::mem::swap(triangles, &mut tmp_triangles);
 	}
 
 	debug_assert!(vertexes.len() == predicted_vertex_count);
+	// Every edge midpoint is shared by both adjacent triangles, so the vertex count
+	// matches Euler's formula for a closed mesh of 20 * 4^n triangles.
+	debug_assert!(vertexes.len() == mesh_vertex_count(subdivision_count));
 	debug_assert!(triangles.len() == predicted_triangle_count);
-	debug_assert!(tmp_triangles.len() == predicted_triangle_count / 4);
+	debug_assert!(triangles.len() == mesh_triangle_count(subdivision_count));
+	debug_assert!(subdivision_count == 0 || tmp_triangles.len() == predicted_triangle_count / 4);
 	debug_assert!(cache.len() == predicted_cache_size);
 
+	if radius != 1.0 {
+		for vertex in vertexes.iter_mut() {
+			*vertex *= radius;
+		}
+	}
+}
+
+/// Keeps every subdivision level generated so far, so a whole chain of LOD meshes costs
+/// no more than generating the most detailed one. Subdividing only appends vertexes,
+/// so all levels share one vertex buffer and each level's vertexes are a prefix of it.
+pub struct MeshBuilder {
+	vertexes: Vec<Vec3>,
+	levels: Vec<Vec<Triangle>>,
+	cache: HashMap<(u32, u32), u32>
+}
+
+impl Default for MeshBuilder {
+	fn default() -> Self {
+		let mut vertexes = Vec::new();
+		let mut triangles = Vec::new();
+		icosahedron(&mut vertexes, &mut triangles);
+		MeshBuilder { vertexes, levels: vec![triangles], cache: HashMap::new() }
+	}
+}
+
+impl MeshBuilder {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// The highest subdivision count generated so far.
+	pub fn level(&self) -> u32 {
+		(self.levels.len() - 1) as u32
+	}
+
+	/// Subdivides further if `subdivision_count` wasn't reached yet and returns the mesh
+	/// `generate_mesh(subdivision_count)` would produce.
+	pub fn subdivide_to(&mut self, subdivision_count: u32) -> (&[Vec3], &[Triangle]) {
+		while self.level() < subdivision_count {
+			let mut triangles = Vec::with_capacity(mesh_triangle_count(self.level() + 1));
+			subdivide_mesh(&mut self.vertexes, &self.levels[self.levels.len() - 1], &mut self.cache, &mut triangles);
+			self.levels.push(triangles);
+			debug_assert!(self.vertexes.len() == mesh_vertex_count(self.level()));
+		}
+		self.mesh(subdivision_count).unwrap()
+	}
+
+	/// The mesh of an already generated subdivision level, `None` above `level()`.
+	pub fn mesh(&self, subdivision_count: u32) -> Option<(&[Vec3], &[Triangle])> {
+		let triangles = self.levels.get(subdivision_count as usize)?;
+		Some((&self.vertexes[..mesh_vertex_count(subdivision_count)], triangles))
+	}
+}
+
+/// Same as `generate_mesh`, plus a smooth-shading normal for every vertex.
+/// Vertices lie on a sphere centered at the origin, so each normal is its normalized position.
+pub fn generate_mesh_with_normals(subdivision_count: u32) -> (Vec<Vec3>, Vec<Vec3>, Vec<Triangle>) {
+	let (vertexes, triangles) = generate_mesh(subdivision_count);
+	let normals = vertexes.iter().map(|v| v.normalize()).collect();
+	(vertexes, normals, triangles)
+}
+
+fn spherical_uv(vertex: Vec3) -> Vec2 {
+	let vertex = vertex.normalize();
+	Vec2::new(0.5 + vertex.z.atan2(vertex.x) / (2.0 * PI), 0.5 - vertex.y.asin() / PI)
+}
+
+/// Same as `generate_mesh`, plus equirectangular texture coordinates for every vertex:
+/// `u = 0.5 + atan2(z, x) / 2π`, `v = 0.5 - asin(y) / π`.
+///
+/// Triangles crossing the antimeridian would otherwise interpolate `u` across the whole
+/// texture, so their vertices on the `u < 0.5` side are duplicated with `u + 1` (rely on
+/// a repeating sampler). The poles have no defined `u`, so every triangle touching a pole
+/// gets its own copy of the pole vertex with `u` set to the average of its other two vertices.
+pub fn generate_mesh_uv(subdivision_count: u32) -> (Vec<Vec3>, Vec<Vec2>, Vec<Triangle>) {
+	let (mut vertexes, mut triangles) = generate_mesh(subdivision_count);
+	let mut uvs: Vec<Vec2> = vertexes.iter().map(|v| spherical_uv(*v)).collect();
+	let mut seam_cache = HashMap::new();
+	let mut used_poles = Vec::new();
+	let is_pole = |vertex: Vec3| vertex.x == 0.0 && vertex.z == 0.0;
+	for triangle in &mut triangles {
+		let mut indexes = [triangle.0, triangle.1, triangle.2];
+		let u: Vec<f32> = indexes.iter()
+			.filter(|&&i| !is_pole(vertexes[i as usize]))
+			.map(|&i| uvs[i as usize].x)
+			.collect();
+		let span = u.iter().cloned().fold(f32::MIN, f32::max) - u.iter().cloned().fold(f32::MAX, f32::min);
+		if span > 0.5 {
+			for index in &mut indexes {
+				if !is_pole(vertexes[*index as usize]) && uvs[*index as usize].x < 0.5 {
+					*index = *seam_cache.entry(*index).or_insert_with(|| {
+						vertexes.push(vertexes[*index as usize]);
+						uvs.push(uvs[*index as usize] + Vec2::X);
+						(vertexes.len() - 1) as u32
+					});
+				}
+			}
+		}
+		for k in 0..3 {
+			let index = indexes[k] as usize;
+			let vertex = vertexes[index];
+			if !is_pole(vertex) {
+				continue;
+			}
+			let u = (uvs[indexes[(k + 1) % 3] as usize].x + uvs[indexes[(k + 2) % 3] as usize].x) / 2.0;
+			if used_poles.contains(&index) {
+				vertexes.push(vertex);
+				uvs.push(Vec2::new(u, uvs[index].y));
+				indexes[k] = (vertexes.len() - 1) as u32;
+			} else {
+				used_poles.push(index);
+				uvs[index].x = u;
+			}
+		}
+		*triangle = (indexes[0], indexes[1], indexes[2]);
+	}
+	(vertexes, uvs, triangles)
+}
+
+/// Same as `generate_mesh`, plus `[latitude, longitude]` in radians for every vertex:
+/// latitude is `asin(y)` in `[-π/2, π/2]` and longitude is `atan2(z, x)` in `[-π, π]`,
+/// matching the angles behind `generate_mesh_uv`.
+///
+/// Longitude is undefined at the two poles (`x` and `z` are both zero there), so it is
+/// reported as 0 for them. Unlike `generate_mesh_uv` no vertexes are duplicated, so
+/// interpolating longitude across the antimeridian or a pole gives meaningless values.
+pub fn generate_mesh_latlong(subdivision_count: u32) -> (Vec<Vec3>, Vec<[f32; 2]>, Vec<Triangle>) {
+	let (vertexes, triangles) = generate_mesh(subdivision_count);
+	let coordinates = vertexes.iter().map(|v| {
+		let v = v.normalize();
+		let longitude = if v.x == 0.0 && v.z == 0.0 { 0.0 } else { v.z.atan2(v.x) };
+		[v.y.clamp(-1.0, 1.0).asin(), longitude]
+	}).collect();
+	(vertexes, coordinates, triangles)
+}
+
+/// Generates a non-indexed triangle soup: three positions per triangle laid out contiguously.
+/// The indexed form shares each vertex between ~6 triangles and needs about 18 bytes per
+/// triangle (half a vertex plus three indices), while the soup takes 36 bytes per triangle.
+pub fn generate_mesh_expanded(subdivision_count: u32) -> Vec<Vec3> {
+	let (vertexes, triangles) = generate_mesh(subdivision_count);
+	triangles_as_positions(&vertexes, &triangles).flatten().collect()
+}
+
+/// Looks up the corner positions of every triangle, e.g. for raycasting or area
+/// computations. Panics if a triangle refers to a vertex that doesn't exist.
+pub fn triangles_as_positions<'a>(vertexes: &'a [Vec3], triangles: &'a [Triangle]) -> impl Iterator<Item = [Vec3; 3]> + 'a {
+	triangles.iter().map(|t| [vertexes[t.0 as usize], vertexes[t.1 as usize], vertexes[t.2 as usize]])
+}
+
+/// Area of every triangle. Icosphere triangles near the 12 original vertexes are noticeably
+/// smaller than the rest, which matters e.g. for uniform sampling.
+pub fn triangle_areas(vertexes: &[Vec3], triangles: &[Triangle]) -> Vec<f32> {
+	triangles_as_positions(vertexes, triangles)
+		.map(|[a, b, c]| (b - a).cross(c - a).length() * 0.5)
+		.collect()
+}
+
+/// Total area of the mesh. For a unit icosphere it approaches the sphere's 4π from below
+/// as the subdivision count grows.
+pub fn surface_area(vertexes: &[Vec3], triangles: &[Triangle]) -> f32 {
+	triangle_areas(vertexes, triangles).iter().sum()
+}
+
+/// Same as `generate_mesh`, flattened for direct GPU upload: positions as interleaved
+/// x, y, z floats and triangles as a plain index list.
+pub fn generate_mesh_raw(subdivision_count: u32) -> (Vec<f32>, Vec<u32>) {
+	let (vertexes, triangles) = generate_mesh(subdivision_count);
+	let positions = vertexes.iter().flat_map(|v| v.to_array()).collect();
+	let indexes = triangles.iter().flat_map(|t| [t.0, t.1, t.2]).collect();
+	(positions, indexes)
+}
+
+/// Same as `generate_mesh`, with positions and triangles as plain arrays that convert
+/// directly into the vector types of nalgebra, cgmath, mint and the like.
+pub fn generate_mesh_arrays(subdivision_count: u32) -> (Vec<[f32; 3]>, Vec<[u32; 3]>) {
+	let (vertexes, triangles) = generate_mesh(subdivision_count);
+	let positions = vertexes.iter().map(|v| v.to_array()).collect();
+	let indexes = triangles.iter().map(|t| [t.0, t.1, t.2]).collect();
+	(positions, indexes)
+}
+
+/// Same as `generate_mesh_raw`, but every vertex is 6 floats: the position followed by
+/// the normal, matching a `#[repr(C)] struct Vertex { pos: [f32; 3], normal: [f32; 3] }`.
+pub fn generate_mesh_pn(subdivision_count: u32) -> (Vec<f32>, Vec<u32>) {
+	let (vertexes, normals, triangles) = generate_mesh_with_normals(subdivision_count);
+	let attributes = vertexes.iter().zip(&normals)
+		.flat_map(|(v, n)| [v.x, v.y, v.z, n.x, n.y, n.z])
+		.collect();
+	let indexes = triangles.iter().flat_map(|t| [t.0, t.1, t.2]).collect();
+	(attributes, indexes)
+}
+
+/// Same as `generate_mesh_uv`, plus normals and tangents for normal mapping. Tangents are
+/// computed with Lengyel's method: per-triangle tangents weighted by the UV deltas are
+/// accumulated per vertex and orthonormalized against the normal. `w` holds the bitangent
+/// handedness. Triangles with degenerate UVs don't contribute, and vertices left without any
+/// contribution get an arbitrary tangent perpendicular to the normal.
+#[allow(clippy::type_complexity)]
+pub fn generate_mesh_tangents(subdivision_count: u32) -> (Vec<Vec3>, Vec<Vec3>, Vec<Vec2>, Vec<Vec4>, Vec<Triangle>) {
+	let (vertexes, uvs, triangles) = generate_mesh_uv(subdivision_count);
+	let normals: Vec<Vec3> = vertexes.iter().map(|v| v.normalize()).collect();
+	let mut tangents = vec![Vec3::ZERO; vertexes.len()];
+	let mut bitangents = vec![Vec3::ZERO; vertexes.len()];
+	for triangle in &triangles {
+		let indexes = [triangle.0 as usize, triangle.1 as usize, triangle.2 as usize];
+		let edge1 = vertexes[indexes[1]] - vertexes[indexes[0]];
+		let edge2 = vertexes[indexes[2]] - vertexes[indexes[0]];
+		let duv1 = uvs[indexes[1]] - uvs[indexes[0]];
+		let duv2 = uvs[indexes[2]] - uvs[indexes[0]];
+		let det = duv1.x * duv2.y - duv2.x * duv1.y;
+		if det.abs() < f32::EPSILON {
+			continue;
+		}
+		let r = 1.0 / det;
+		let tangent = (edge1 * duv2.y - edge2 * duv1.y) * r;
+		let bitangent = (edge2 * duv1.x - edge1 * duv2.x) * r;
+		for index in indexes {
+			tangents[index] += tangent;
+			bitangents[index] += bitangent;
+		}
+	}
+	let tangents = normals.iter().zip(tangents.iter().zip(&bitangents)).map(|(&n, (&t, &b))| {
+		let tangent = (t - n * n.dot(t)).normalize_or_zero();
+		let tangent = if tangent == Vec3::ZERO { n.any_orthonormal_vector() } else { tangent };
+		let handedness = if n.cross(t).dot(b) < 0.0 { -1.0 } else { 1.0 };
+		tangent.extend(handedness)
+	}).collect();
+	(vertexes, normals, uvs, tangents, triangles)
+}
+
+/// Converts triangles into a line list with two indexes per edge. Edges shared by
+/// adjacent triangles are emitted once, in the order they are first encountered.
+pub fn generate_wireframe_indices(triangles: &[Triangle]) -> Vec<u32> {
+	let mut seen = HashSet::with_capacity(triangles.len() * 3 / 2);
+	let mut indexes = Vec::with_capacity(triangles.len() * 3);
+	for triangle in triangles {
+		for (first, second) in [(triangle.0, triangle.1), (triangle.1, triangle.2), (triangle.2, triangle.0)] {
+			if seen.insert((min(first, second), max(first, second))) {
+				indexes.push(first);
+				indexes.push(second);
+			}
+		}
+	}
+	indexes
+}
+
+/// Finds the neighbours of every triangle: entry `i` of a triangle's adjacency is the
+/// triangle sharing its `i`-th edge (`(a, b)`, `(b, c)`, `(c, a)`), or -1 if no other
+/// triangle has that edge. An edge shared by more than two triangles only links
+/// the first two of them.
+pub fn build_adjacency(triangles: &[Triangle]) -> Vec<[i32; 3]> {
+	let mut adjacency = vec![[-1; 3]; triangles.len()];
+	let mut edges = HashMap::<(u32, u32), (usize, usize)>::with_capacity(triangles.len() * 3 / 2);
+	for (i, triangle) in triangles.iter().enumerate() {
+		for (edge, (first, second)) in [(triangle.0, triangle.1), (triangle.1, triangle.2), (triangle.2, triangle.0)].into_iter().enumerate() {
+			let key = (min(first, second), max(first, second));
+			match edges.get(&key) {
+				Some(&(other, other_edge)) => {
+					if adjacency[other][other_edge] == -1 {
+						adjacency[other][other_edge] = i as i32;
+						adjacency[i][edge] = other as i32;
+					}
+				}
+				None => {
+					edges.insert(key, (i, edge));
+				}
+			}
+		}
+	}
+	adjacency
+}
+
+/// Builds the dual of a closed mesh, e.g. the Goldberg polyhedron of an icosphere: every
+/// triangle becomes a vertex at its centroid (pushed out to the triangle's average corner
+/// distance from the origin) and every original vertex becomes a face joining the centers
+/// of the triangles around it. For an icosphere that is 12 pentagons and hexagons elsewhere.
+///
+/// Face `i` belongs to vertex `i` and lists dual vertexes in the same winding order as the
+/// triangles. If the fan around a vertex isn't closed (a hole or
+/// a non-manifold edge), its face stops where the walk around it breaks off; vertexes that
+/// no triangle uses get an empty face.
+pub fn generate_dual(vertexes: &[Vec3], triangles: &[Triangle]) -> (Vec<Vec3>, Vec<Vec<u32>>) {
+	let centers = triangles_as_positions(vertexes, triangles).map(|[a, b, c]| {
+		let radius = (a.length() + b.length() + c.length()) / 3.0;
+		((a + b + c) / 3.0).normalize_or_zero() * radius
+	}).collect();
+
+	let mut edges = HashMap::<(u32, u32), u32>::with_capacity(triangles.len() * 3);
+	let mut first_triangles = vec![None; vertexes.len()];
+	for (i, triangle) in triangles.iter().enumerate() {
+		for (first, second) in [(triangle.0, triangle.1), (triangle.1, triangle.2), (triangle.2, triangle.0)] {
+			edges.insert((first, second), i as u32);
+			first_triangles[first as usize].get_or_insert(i as u32);
+		}
+	}
+
+	let faces = first_triangles.iter().enumerate().map(|(vertex, &first)| {
+		let vertex = vertex as u32;
+		let mut face = Vec::with_capacity(6);
+		let mut current = first;
+		while let Some(i) = current {
+			face.push(i);
+			// Rotated to `(vertex, a, b)`, the next triangle around `vertex` is the one on the
+			// other side of `b -> vertex`, where that edge runs `vertex -> b`.
+			let (a, b, c) = triangles[i as usize];
+			let next_corner = if a == vertex { c } else if b == vertex { a } else { b };
+			current = edges.get(&(vertex, next_corner)).copied()
+				.filter(|&next| Some(next) != first && face.len() < triangles.len());
+		}
+		face
+	}).collect();
+
+	(centers, faces)
+}
+
+/// Concatenates two meshes: `b`'s vertexes are appended after `a`'s and its triangle
+/// indexes are offset by `a`'s vertex count, e.g. to draw several spheres in one call.
+pub fn merge(a: (Vec<Vec3>, Vec<Triangle>), b: (Vec<Vec3>, Vec<Triangle>)) -> (Vec<Vec3>, Vec<Triangle>) {
+	let (mut vertexes, mut triangles) = a;
+	let offset = vertexes.len() as u32;
+	vertexes.extend(b.0);
+	triangles.extend(b.1.into_iter().map(|(i0, i1, i2)| (i0 + offset, i1 + offset, i2 + offset)));
 	(vertexes, triangles)
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn vertex_and_triangle_count() {
+		for n in 0..=6 {
+			let (vertexes, triangles) = generate_mesh(n);
+			assert_eq!(vertexes.len(), 10 * 4usize.pow(n) + 2, "subdivision count {}", n);
+			// Every subdivision splits each triangle into 4, starting from the 20 of the icosahedron.
+			assert_eq!(triangles.len(), 20 * 4usize.pow(n), "subdivision count {}", n);
+			assert_eq!(generate_mesh_result(n).triangles.len(), triangles.len());
+		}
+	}
+}
@@ -0,0 +1,20 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(not(any(feature = "std", feature = "libm")))]
+compile_error!("either the `std` or the `libm` feature must be enabled");
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+use ahash::{AHashMap as HashMap, AHashSet as HashSet};
+#[cfg(not(feature = "std"))]
+use hashbrown::{HashMap, HashSet};
+
+#[cfg(not(any(feature = "std", test)))]
+mod math;
+pub mod icosphere;
+pub mod cubesphere;
+pub mod font_loader;
+pub mod font_writer;
+#[cfg(feature = "wasm")]
+pub mod wasm;
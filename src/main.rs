@@ -1,34 +1,275 @@
+// Quick smoke benchmark. For statistically sound measurements use `cargo bench`,
+// which runs the criterion benchmarks under benches/.
+
+use std::hint::black_box;
 use std::time::Instant;
 
-mod icosphere;
-mod font_loader;
+use rust_benchmark::{font_loader, font_writer, icosphere};
+
+enum OutputFormat {
+	Text,
+	Json
+}
+
+struct Args {
+	icosphere_iters: usize,
+	font_iters: usize,
+	warmup_iters: usize,
+	fonts: Vec<String>,
+	format: OutputFormat
+}
+
+fn exit_with_usage() -> ! {
+	eprintln!("Usage: rust_benchmark [--icosphere-iters N] [--font-iters N] [--warmup-iters N] [--font FILE.pf2]... [--format text|json]");
+	std::process::exit(2);
+}
+
+fn parse_args() -> Args {
+	let mut args = Args {
+		icosphere_iters: 10000,
+		font_iters: 1000,
+		warmup_iters: 200,
+		fonts: Vec::new(),
+		format: OutputFormat::Text
+	};
+	let mut iter = std::env::args().skip(1);
+	while let Some(arg) = iter.next() {
+		if arg == "--format" {
+			args.format = match iter.next().as_deref() {
+				Some("text") => OutputFormat::Text,
+				Some("json") => OutputFormat::Json,
+				_ => exit_with_usage()
+			};
+			continue;
+		}
+		if arg == "--font" {
+			args.fonts.push(iter.next().unwrap_or_else(|| exit_with_usage()));
+			continue;
+		}
+		let value = match arg.as_str() {
+			"--icosphere-iters" => &mut args.icosphere_iters,
+			"--font-iters" => &mut args.font_iters,
+			"--warmup-iters" => &mut args.warmup_iters,
+			_ => exit_with_usage()
+		};
+		*value = match iter.next().and_then(|s| s.parse().ok()) {
+			Some(count) if count > 0 || arg == "--warmup-iters" => count,
+			_ => exit_with_usage()
+		};
+	}
+	args
+}
+
+struct Stats {
+	min: f64,
+	max: f64,
+	median: f64,
+	mean: f64,
+	stddev: f64
+}
+
+impl Stats {
+	fn to_text(&self) -> String {
+		format!(
+			"mean {:.1} us, median {:.1} us, min {:.1} us, max {:.1} us, stddev {:.1} us",
+			self.mean, self.median, self.min, self.max, self.stddev
+		)
+	}
+
+	fn to_json(&self) -> String {
+		format!(
+			"{{\"min\": {:.3}, \"max\": {:.3}, \"median\": {:.3}, \"mean\": {:.3}, \"stddev\": {:.3}}}",
+			self.min, self.max, self.median, self.mean, self.stddev
+		)
+	}
+}
+
+fn measure<F: FnMut() -> usize>(warmup: usize, count: usize, mut f: F) -> Stats {
+	for _ in 0..warmup {
+		black_box(f());
+	}
+	let mut samples: Vec<f64> = (0..count).map(|_| {
+		let start = Instant::now();
+		black_box(f());
+		start.elapsed().as_secs_f64() * 1e6
+	}).collect();
+	samples.sort_by(f64::total_cmp);
+	let mean = samples.iter().sum::<f64>() / count as f64;
+	let variance = samples.iter().map(|s| (s - mean) * (s - mean)).sum::<f64>() / count as f64;
+	let median = if count.is_multiple_of(2) {
+		(samples[count / 2 - 1] + samples[count / 2]) / 2.0
+	} else {
+		samples[count / 2]
+	};
+	Stats {
+		min: samples[0],
+		max: samples[count - 1],
+		median,
+		mean,
+		stddev: variance.sqrt()
+	}
+}
+
+const ICOSPHERE_LEVELS: std::ops::RangeInclusive<u32> = 1..=6;
+/// The level `--icosphere-iters` applies to and the one reported as `icosphere_us`.
+const ICOSPHERE_REFERENCE_LEVEL: u32 = 4;
 
 #[inline(never)]
-fn run_test_icosphere() -> usize {
-	let (vertexes, triangles) = icosphere::generate_mesh(4);
+fn run_test_icosphere(level: u32) -> usize {
+	let (vertexes, triangles) = icosphere::generate_mesh(level);
 	vertexes.len() * triangles.len()
 }
 
 #[inline(never)]
-fn run_test_font_loader(data: &[u8]) -> usize {
-	let (texture_data, glyphs) = font_loader::PF2Loader::new(data).load().unwrap();
-	texture_data.len() * glyphs.len()
+fn run_test_font_loader(data: &[u8], timings: &mut Vec<font_loader::LoadTimings>) -> usize {
+	let mut loader = font_loader::PF2Loader::new(data);
+	let font = loader.load().unwrap();
+	timings.push(loader.timings());
+	font.texture().len() * font.glyphs().len()
+}
+
+struct FontResult<'a> {
+	name: &'a str,
+	glyph_count: usize,
+	stats: Stats,
+	steps: [(&'static str, f64); 5]
+}
+
+/// Mean duration of every loading step in microseconds.
+fn mean_steps(timings: &[font_loader::LoadTimings]) -> [(&'static str, f64); 5] {
+	let mean = |step: fn(&font_loader::LoadTimings) -> std::time::Duration| {
+		timings.iter().map(|t| step(t).as_secs_f64() * 1e6).sum::<f64>() / timings.len() as f64
+	};
+	[
+		("parse", mean(|t| t.parse)),
+		("read", mean(|t| t.read)),
+		("layout", mean(|t| t.layout)),
+		("allocation", mean(|t| t.allocation)),
+		("rasterization", mean(|t| t.rasterization))
+	]
+}
+
+/// A font with as many glyphs as a CJK font, filled with pseudo-random bitmaps, since the
+/// only bundled font is a small Latin/Cyrillic one.
+fn synthetic_cjk_font() -> Vec<u8> {
+	const SIZE: u16 = 24;
+	let mut writer = font_writer::PF2Writer::new().name("Synthetic CJK 24").point_size(SIZE).ascent(20).descent(4);
+	let mut state = 0x2545F491u32;
+	let mut pixels = vec![false; SIZE as usize * SIZE as usize];
+	for code_point in 0x4E00..0x4E00 + 20000 {
+		for pixel in &mut pixels {
+			// xorshift32
+			state ^= state << 13;
+			state ^= state >> 17;
+			state ^= state << 5;
+			*pixel = state & 3 == 0;
+		}
+		writer = writer.glyph(code_point, font_writer::PF2GlyphImage {
+			width: SIZE,
+			height: SIZE,
+			x_offset: 0,
+			y_offset: -4,
+			device_width: SIZE as i16,
+			pixels: &pixels
+		});
+	}
+	writer.write()
+}
+
+/// Loads the font once, so a broken `--font` file is reported before benchmarking it.
+fn glyph_count(data: &[u8]) -> Result<usize, font_loader::PF2Error> {
+	let mut loader = font_loader::PF2Loader::new(data);
+	loader.load()?;
+	Ok(loader.available_codepoints().count())
+}
+
+/// Quotes `s` as a JSON string; Rust's `{:?}` escapes don't all parse as JSON.
+fn json_string(s: &str) -> String {
+	let mut json = String::from("\"");
+	for c in s.chars() {
+		match c {
+			'"' => json.push_str("\\\""),
+			'\\' => json.push_str("\\\\"),
+			c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+			c => json.push(c)
+		}
+	}
+	json.push('"');
+	json
 }
 
 fn main() {
-	let start = Instant::now();
-	let count = 10000;
-	for _ in 0..count {
-		run_test_icosphere();
+	let args = parse_args();
+
+	// Every level quadruples the work, so levels above the reference one run fewer iterations.
+	let icosphere_levels: Vec<(u32, Stats)> = ICOSPHERE_LEVELS.map(|level| {
+		let shift = 2 * level.saturating_sub(ICOSPHERE_REFERENCE_LEVEL);
+		let iters = (args.icosphere_iters >> shift).max(1);
+		let warmup = args.warmup_iters >> shift;
+		(level, measure(warmup, iters, || run_test_icosphere(level)))
+	}).collect();
+	let icosphere = &icosphere_levels.iter()
+		.find(|(level, _)| *level == ICOSPHERE_REFERENCE_LEVEL)
+		.unwrap().1;
+	let reference_font: &[u8] = include_bytes!("../assets/DroidSans-32.pf2");
+	let mut fonts = vec![
+		(String::from("DroidSans-32.pf2"), reference_font.to_vec()),
+		(String::from("synthetic-cjk-24"), synthetic_cjk_font())
+	];
+	for path in &args.fonts {
+		let data = std::fs::read(path).unwrap_or_else(|e| {
+			eprintln!("Cannot read {}: {}", path, e);
+			std::process::exit(1);
+		});
+		fonts.push((path.clone(), data));
 	}
-	let elapsed = start.elapsed();
-	println!("Mesh generation time: {} us", elapsed.as_micros() / count);
+	let fonts: Vec<(String, Vec<u8>, usize)> = fonts.into_iter().map(|(name, data)| {
+		let glyph_count = glyph_count(&data).unwrap_or_else(|e| {
+			eprintln!("Cannot load {}: {}", name, e);
+			std::process::exit(1);
+		});
+		(name, data, glyph_count)
+	}).collect();
+	// `--font-iters` applies to the bundled font; larger fonts run proportionally fewer iterations.
+	let reference_glyph_count = fonts[0].2;
+	let font_results: Vec<FontResult> = fonts.iter().map(|(name, data, glyph_count)| {
+		let glyph_count = *glyph_count;
+		let scale = glyph_count.div_ceil(reference_glyph_count).max(1);
+		let iters = (args.font_iters / scale).max(1);
+		let mut timings = Vec::new();
+		let stats = measure(args.warmup_iters / scale, iters, || run_test_font_loader(data, &mut timings));
+		FontResult { name, glyph_count, stats, steps: mean_steps(&timings[timings.len() - iters..]) }
+	}).collect();
+	let font_loader = &font_results[0].stats;
 
-	let start = Instant::now();
-	let count = 1000;
-	for _ in 0..count {
-		run_test_font_loader(include_bytes!("../assets/DroidSans-32.pf2"));
+	match args.format {
+		OutputFormat::Text => {
+			for (level, stats) in &icosphere_levels {
+				println!("Mesh generation time (level {}): {}", level, stats.to_text());
+			}
+			for result in &font_results {
+				println!("Font loading time ({}, {} glyphs): {}", result.name, result.glyph_count, result.stats.to_text());
+				let steps: Vec<String> = result.steps.iter().map(|(step, us)| format!("{} {:.1} us", step, us)).collect();
+				println!("  mean per step: {}", steps.join(", "));
+			}
+		}
+		OutputFormat::Json => {
+			let levels: Vec<String> = icosphere_levels.iter()
+				.map(|(level, stats)| format!("\"{}\": {}", level, stats.to_json()))
+				.collect();
+			let fonts: Vec<String> = font_results.iter()
+				.map(|result| {
+					let steps: Vec<String> = result.steps.iter().map(|(step, us)| format!("\"{}\": {:.3}", step, us)).collect();
+					format!(
+						"{{\"name\": {}, \"glyphs\": {}, \"stats\": {}, \"steps_us\": {{{}}}}}",
+						json_string(result.name), result.glyph_count, result.stats.to_json(), steps.join(", ")
+					)
+				})
+				.collect();
+			println!(
+				"{{\"icosphere_us\": {:.3}, \"font_loader_us\": {:.3}, \"icosphere\": {}, \"icosphere_levels\": {{{}}}, \"font_loader\": {}, \"fonts\": [{}]}}",
+				icosphere.mean, font_loader.mean, icosphere.to_json(), levels.join(", "), font_loader.to_json(), fonts.join(", ")
+			);
+		}
 	}
-	let elapsed = start.elapsed();
-	println!("Font loading time: {} us", elapsed.as_micros() / count);
 }
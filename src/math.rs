@@ -0,0 +1,35 @@
+// `core` has no float functions which need a math library, so without std they come from libm.
+// Whenever std ends up linked anyway, its inherent float methods take precedence and this trait
+// goes unused: tests link it, and so does `--all-targets`, where dev-dependencies turn on the
+// std features of crates shared with the library.
+
+#[allow(dead_code)]
+pub(crate) trait FloatExt {
+	fn sqrt(self) -> Self;
+	fn ceil(self) -> Self;
+	fn round(self) -> Self;
+	fn asin(self) -> Self;
+	fn atan2(self, other: Self) -> Self;
+}
+
+impl FloatExt for f32 {
+	fn sqrt(self) -> Self {
+		libm::sqrtf(self)
+	}
+
+	fn ceil(self) -> Self {
+		libm::ceilf(self)
+	}
+
+	fn round(self) -> Self {
+		libm::roundf(self)
+	}
+
+	fn asin(self) -> Self {
+		libm::asinf(self)
+	}
+
+	fn atan2(self, other: Self) -> Self {
+		libm::atan2f(self, other)
+	}
+}
@@ -0,0 +1,67 @@
+//! JavaScript bindings for running the loader and the mesh generator in a browser.
+
+use glam::Vec2;
+use js_sys::{Array, Float32Array, Object, Reflect, Uint8ClampedArray};
+use rgb::ComponentBytes;
+use wasm_bindgen::prelude::*;
+
+use crate::font_loader::{FontGlyph, PF2Loader};
+use crate::icosphere;
+
+fn set(object: &Object, key: &str, value: impl Into<JsValue>) {
+	Reflect::set(object, &JsValue::from_str(key), &value.into()).unwrap_throw();
+}
+
+fn vec2(value: Vec2) -> Array {
+	Array::of2(&value.x.into(), &value.y.into())
+}
+
+fn glyph_object(code_point: u32, glyph: &FontGlyph) -> Object {
+	let object = Object::new();
+	set(&object, "codePoint", code_point);
+	set(&object, "texCoord", vec2(glyph.tex_coord()));
+	set(&object, "texSize", vec2(glyph.tex_size()));
+	set(&object, "offset", vec2(glyph.offset()));
+	set(&object, "size", vec2(glyph.size()));
+	set(&object, "width", glyph.width());
+	set(&object, "advancePx", glyph.advance_px());
+	object
+}
+
+/// Loads a PFF2 font and returns `{ texture, width, height, metrics, glyphs }`, where `texture`
+/// is a `Uint8ClampedArray` of RGBA texels that can be passed to `ImageData` directly and
+/// `glyphs` is an array of objects with the `FontGlyph` fields in camelCase plus `codePoint`.
+#[wasm_bindgen(js_name = loadFont)]
+pub fn load_font(data: &[u8]) -> Result<JsValue, JsError> {
+	let font = PF2Loader::new(data).load()?;
+	let (width, height) = font.size();
+
+	let metrics = Object::new();
+	set(&metrics, "ascent", font.metrics().ascent);
+	set(&metrics, "descent", font.metrics().descent);
+	set(&metrics, "pointSize", font.metrics().point_size);
+	set(&metrics, "maxWidth", font.metrics().max_width);
+	set(&metrics, "maxHeight", font.metrics().max_height);
+
+	let glyphs: Array = font.glyphs_sorted().map(|(code_point, glyph)| glyph_object(code_point, glyph)).collect();
+
+	let result = Object::new();
+	set(&result, "texture", Uint8ClampedArray::from(font.texture().as_bytes()));
+	set(&result, "width", width as u32);
+	set(&result, "height", height as u32);
+	set(&result, "metrics", metrics);
+	set(&result, "glyphs", glyphs);
+	Ok(result.into())
+}
+
+/// Generates a unit icosphere as a flat list of triangle corner positions (nine floats per
+/// triangle), ready for `gl.drawArrays(gl.TRIANGLES, ...)` without an index buffer.
+#[wasm_bindgen(js_name = generateIcosphere)]
+pub fn generate_icosphere(level: u32) -> Float32Array {
+	let (vertexes, triangles) = icosphere::generate_mesh(level);
+	let positions: Vec<f32> = triangles.iter()
+		.flat_map(|&(a, b, c)| [a, b, c])
+		.flat_map(|i| vertexes[i as usize].to_array())
+		.collect();
+	Float32Array::from(positions.as_slice())
+}